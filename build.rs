@@ -1,5 +1,7 @@
 use std::{
+	collections::hash_map::DefaultHasher,
 	env, fs,
+	hash::{Hash, Hasher},
 	path::{Path, PathBuf},
 };
 
@@ -7,6 +9,33 @@ fn main() {
 	// Re-runs script if any files in res are changed
 	println!("cargo:rerun-if-changed=res/*");
 	copy_single_folder("res");
+
+	// Identifies the sources spicy was built from, the build cache is dropped when they change
+	println!("cargo:rerun-if-changed=src");
+	println!("cargo:rustc-env=SPICY_SOURCE_HASH={}", hash_sources("src"));
+}
+
+fn hash_sources(relative_path: &str) -> String {
+	let mut files = vec![];
+	collect_files(&Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join(relative_path), &mut files);
+	files.sort();
+	let mut hasher = DefaultHasher::new();
+	for file in files {
+		file.hash(&mut hasher);
+		fs::read(&file).unwrap().hash(&mut hasher);
+	}
+	format!("{:016x}", hasher.finish())
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+	for entry in fs::read_dir(dir).unwrap() {
+		let path = entry.unwrap().path();
+		if path.is_dir() {
+			collect_files(&path, files);
+		} else {
+			files.push(path);
+		}
+	}
 }
 
 fn copy_single_folder(relative_path: &str) {
@@ -26,7 +55,7 @@ fn get_output_path() -> PathBuf {
 	let path = Path::new(&manifest_dir_string)
 		.join("target")
 		.join(build_type);
-	return PathBuf::from(path);
+	path
 }
 
 fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) {
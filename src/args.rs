@@ -1,4 +1,4 @@
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
 /// A fictional versioning CLI
 #[derive(Debug, Parser)] // requires `derive` feature
@@ -8,6 +8,23 @@ use clap::{command, Parser, Subcommand};
 pub struct Args {
 	#[command(subcommand)]
 	pub command: Commands,
+	#[arg(
+		long,
+		global = true,
+		value_enum,
+		default_value_t = MessageFormat::Human,
+		help = "How validation problems are reported"
+	)]
+	pub message_format: MessageFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum MessageFormat {
+	// Colored summary grouped by file
+	#[default]
+	Human,
+	// One JSON object per line, for editors and CI
+	Json,
 }
 
 #[derive(Debug, Subcommand)]
@@ -19,9 +36,71 @@ pub enum Commands {
 	#[command(about = "Runs the editor")]
 	Edit,
 	#[command(about = "Runs the game with OpenMW")]
-	Run,
-	#[command(about = "Compiles an out.omwgame file to run the game")]
+	Run(RunArgs),
+	#[command(about = "Compiles the plugin described by spicy.toml to run the game")]
 	Compile,
-	#[command(about = "Decompiles the out.omwgame in the build directory")]
+	#[command(about = "Recompiles records and assets whenever they change")]
+	Watch,
+	#[command(about = "Validates all records without building the plugin")]
+	Check,
+	#[command(about = "Creates a new record with default values in common/data")]
+	Add {
+		#[arg(help = "Record type, for example Weapon or Npc")]
+		record_type: String,
+		#[arg(
+			allow_hyphen_values = true,
+			help = "Id of the record - the grid like `0,-1` for exterior cells and landscapes"
+		)]
+		id: String,
+	},
+	#[command(about = "Renames a record and updates every reference to it")]
+	RenameId {
+		old_id: String,
+		new_id: String,
+		#[arg(long = "type", help = "Record type, needed when records of several types have the id")]
+		record_type: Option<String>,
+	},
+	#[command(about = "Lists every record and field that refers to the id")]
+	Refs {
+		id: String,
+		#[arg(long = "type", help = "Record type, needed when records of several types have the id")]
+		record_type: Option<String>,
+	},
+	#[command(about = "Shows record counts, asset sizes and assets that are unused or missing")]
+	Stats,
+	#[command(about = "Rewrites every record file in its canonical form")]
+	Fmt {
+		#[arg(long, help = "Only list the files that aren't formatted, without changing them")]
+		check: bool,
+	},
+	#[command(about = "Three-way merge of a record file, meant to be called by git as a merge driver")]
+	MergeDriver {
+		base: String,
+		ours: String,
+		theirs: String,
+	},
+	#[command(
+		about = "Shows record and field level differences between two plugins or record directories"
+	)]
+	Diff { old: String, new: String },
+	#[command(about = "Decompiles the compiled plugin in the build directory")]
 	Decompile { input_path: Option<String> },
+	#[command(about = "Brings back the records from before the last decompile")]
+	Undo,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct RunArgs {
+	#[arg(long, help = "Name of a launch profile from spicy.toml")]
+	pub profile: Option<String>,
+	#[arg(long, help = "Runs the last compiled plugin without compiling again")]
+	pub no_compile: bool,
+	#[arg(long, help = "Starts at the main menu instead of a new game")]
+	pub menu: bool,
+	#[arg(long, value_name = "SAVE", help = "Loads a saved game")]
+	pub load: Option<String>,
+	#[arg(long, value_name = "CELL", help = "Starts a new game in this cell")]
+	pub start_cell: Option<String>,
+	#[arg(last = true, help = "Arguments passed to openmw as they are, after `--`")]
+	pub openmw_args: Vec<String>,
 }
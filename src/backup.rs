@@ -0,0 +1,99 @@
+use std::{
+	env, fs,
+	path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{dae::collect_files, records::get_record_types};
+
+// Decompiled records are written here first and only replace common/data once they are complete
+const STAGING_DIR: &str = "cache/decompile_staging";
+// common/data from before the last decompile, `spicy undo` swaps it back
+const BACKUP_DIR: &str = "cache/decompile_backup";
+const UNDO_DIR: &str = "cache/decompile_undo";
+
+fn get_path(subpath: &str) -> PathBuf {
+	let mut path = env::current_dir().unwrap();
+	path.push(subpath);
+	path
+}
+
+// Creates an empty staging directory with a directory for every record type
+pub fn create_staging_dir() -> anyhow::Result<PathBuf> {
+	let staging_path = get_path(STAGING_DIR);
+	if staging_path.exists() {
+		fs::remove_dir_all(&staging_path)?;
+	}
+	// The Header is derived from spicy.toml, it has no directory
+	for record_type in get_record_types().iter().filter(|record_type| *record_type != "Header") {
+		fs::create_dir_all(staging_path.join(record_type))?;
+	}
+	Ok(staging_path)
+}
+
+// Swaps the staging directory in place of common/data, keeping the old records as the backup
+pub fn replace_data_dir(staging_path: &Path) -> anyhow::Result<()> {
+	let data_path = get_path("common/data");
+	// Files that aren't records, like notes next to the record files, are carried over
+	if data_path.exists() {
+		let mut files = vec![];
+		collect_files(&data_path, &mut files)?;
+		for file in files {
+			if file.extension().unwrap_or_default() == "json" {
+				continue;
+			}
+			let target = staging_path.join(file.strip_prefix(&data_path)?);
+			if !target.exists() {
+				fs::create_dir_all(target.parent().unwrap())?;
+				fs::copy(&file, &target)?;
+			}
+		}
+	}
+
+	let backup_path = get_path(BACKUP_DIR);
+	if backup_path.exists() {
+		fs::remove_dir_all(&backup_path)?;
+	}
+	swap(&data_path, staging_path, &backup_path)
+}
+
+// Brings back the records from before the last decompile
+// The replaced records become the backup, so running it again redoes the decompile
+pub fn undo() -> anyhow::Result<()> {
+	let backup_path = get_path(BACKUP_DIR);
+	if !backup_path.exists() {
+		return Err(anyhow!("there is no decompile to undo"));
+	}
+	let data_path = get_path("common/data");
+	let undo_path = get_path(UNDO_DIR);
+	if undo_path.exists() {
+		fs::remove_dir_all(&undo_path)?;
+	}
+	swap(&data_path, &backup_path, &undo_path)?;
+	fs::rename(&undo_path, &backup_path)?;
+
+	println!("Restored common/data from before the last decompile");
+	println!("Run `spicy undo` again to bring the decompiled records back");
+	Ok(())
+}
+
+// Moves `target` to `old_target` and `replacement` to `target`
+// Renames within the project directory are atomic, if the second one fails the first is reverted
+fn swap(target: &Path, replacement: &Path, old_target: &Path) -> anyhow::Result<()> {
+	fs::create_dir_all(old_target.parent().unwrap())?;
+	if target.exists() {
+		fs::rename(target, old_target)?;
+	}
+	if let Err(err) = fs::rename(replacement, target) {
+		if old_target.exists() {
+			fs::rename(old_target, target)?;
+		}
+		return Err(anyhow!(
+			"failed to replace {}: {}",
+			target.to_string_lossy(),
+			err
+		));
+	}
+	Ok(())
+}
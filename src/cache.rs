@@ -0,0 +1,247 @@
+use std::{
+	collections::{BTreeMap, HashSet},
+	env, fs,
+	path::{Path, PathBuf},
+	time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{constants::PROJECT_MANIFEST_FILE, diagnostics::Diagnostic};
+
+const BUILD_CACHE_FILE: &str = "cache/build_cache.json";
+const BUILD_MANIFEST_FILE: &str = "build/build-manifest.json";
+
+// Remembers what previous builds produced, so unchanged inputs can be skipped
+// The whole cache is dropped whenever spicy itself or spicy.toml changes
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BuildCache {
+	spicy_version: String,
+	// Changes with every change to the sources of spicy, even without a version bump
+	#[serde(default)]
+	source_hash: String,
+	manifest_hash: String,
+	// Parsed and validated records, keyed by the path of their file
+	records: BTreeMap<String, CachedRecord>,
+	// Source assets that were already converted or copied to the build directory
+	assets: BTreeMap<String, FileStamp>,
+	// The tes3conv input that produced the current plugin, along with the plugin itself
+	output: Option<CachedOutput>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedRecord {
+	stamp: FileStamp,
+	pub record: Value,
+	// Problems found by the checks that only depend on the content of the file
+	pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedOutput {
+	input_hash: String,
+	output_hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+	len: u64,
+	// Nanoseconds since the unix epoch
+	modified: u128,
+	hash: String,
+}
+
+impl FileStamp {
+	pub fn read(path: &Path) -> anyhow::Result<Self> {
+		let (len, modified) = get_len_and_modified(path)?;
+		Ok(FileStamp {
+			len,
+			modified,
+			hash: hash_bytes(&fs::read(path)?),
+		})
+	}
+
+	// Checks whether the file still has the content this stamp was made from,
+	// returning the stamp with the current size and modification time if it does
+	// The content is only hashed when the size or modification time differs
+	fn refresh(&self, path: &Path) -> Option<FileStamp> {
+		let (len, modified) = get_len_and_modified(path).ok()?;
+		if len == self.len && modified == self.modified {
+			return Some(self.clone());
+		}
+		let content = fs::read(path).ok()?;
+		if hash_bytes(&content) == self.hash {
+			return Some(FileStamp {
+				len,
+				modified,
+				hash: self.hash.clone(),
+			});
+		}
+
+		None
+	}
+}
+
+impl BuildCache {
+	// Loads the cache of the previous build, or starts an empty one if it can't be used
+	pub fn load() -> Self {
+		let version = env!("CARGO_PKG_VERSION").to_string();
+		let source_hash = env!("SPICY_SOURCE_HASH").to_string();
+		let manifest_hash = get_manifest_hash();
+
+		let cache: Option<BuildCache> = fs::read_to_string(get_cache_path())
+			.ok()
+			.and_then(|content| serde_json::from_str(&content).ok());
+		match cache {
+			Some(cache)
+				if cache.spicy_version == version
+					&& cache.source_hash == source_hash
+					&& cache.manifest_hash == manifest_hash =>
+			{
+				cache
+			}
+			_ => {
+				eprintln!("Build cache is missing or outdated, doing a full rebuild");
+				BuildCache {
+					spicy_version: version,
+					source_hash,
+					manifest_hash,
+					..Default::default()
+				}
+			}
+		}
+	}
+
+	pub fn save(&self) -> anyhow::Result<()> {
+		let path = get_cache_path();
+		fs::create_dir_all(path.parent().unwrap())?;
+		fs::write(path, serde_json::to_string(self)?)?;
+		Ok(())
+	}
+
+	// Returns the cached record of a file along with its current stamp,
+	// if the file didn't change since it was cached
+	// Doesn't modify the cache, so it can be used from many threads at once
+	pub fn get_record(&self, path: &Path) -> Option<(FileStamp, &CachedRecord)> {
+		let entry = self.records.get(&path.to_string_lossy().to_string())?;
+		let stamp = entry.stamp.refresh(path)?;
+		Some((stamp, entry))
+	}
+
+	// Remembers the current size and modification time of an unchanged record file
+	pub fn update_record_stamp(&mut self, path: &Path, stamp: FileStamp) {
+		if let Some(entry) = self.records.get_mut(&path.to_string_lossy().to_string()) {
+			entry.stamp = stamp;
+		}
+	}
+
+	// Drops records of files that no longer exist
+	pub fn forget_missing_records(&mut self, files: &[PathBuf]) {
+		let files: HashSet<String> = files
+			.iter()
+			.map(|file| file.to_string_lossy().to_string())
+			.collect();
+		self.records.retain(|path, _| files.contains(path));
+	}
+
+	pub fn insert_record(
+		&mut self,
+		path: &Path,
+		stamp: FileStamp,
+		record: Value,
+		diagnostics: Vec<Diagnostic>,
+	) {
+		self.records.insert(
+			path.to_string_lossy().to_string(),
+			CachedRecord {
+				stamp,
+				record,
+				diagnostics,
+			},
+		);
+	}
+
+	// Checks whether an asset was already converted and its output is still in place
+	pub fn is_asset_fresh(&mut self, source: &Path, target: &Path) -> bool {
+		if !target.exists() {
+			return false;
+		}
+		let key = source.to_string_lossy().to_string();
+		let Some(stamp) = self.assets.get(&key).and_then(|stamp| stamp.refresh(source)) else {
+			return false;
+		};
+		self.assets.insert(key, stamp);
+		true
+	}
+
+	pub fn insert_asset(&mut self, source: &Path) -> anyhow::Result<()> {
+		self.assets.insert(
+			source.to_string_lossy().to_string(),
+			FileStamp::read(source)?,
+		);
+		Ok(())
+	}
+
+	// Checks whether the plugin at `output_path` was built from exactly this tes3conv input
+	pub fn is_output_fresh(&self, input_hash: &str, output_path: &Path) -> bool {
+		let Some(output) = self.output.as_ref() else {
+			return false;
+		};
+		if output.input_hash != input_hash {
+			return false;
+		}
+		match fs::read(output_path) {
+			Ok(content) => hash_bytes(&content) == output.output_hash,
+			Err(_) => false,
+		}
+	}
+
+	pub fn set_output(&mut self, input_hash: String, output_path: &Path) -> anyhow::Result<()> {
+		self.output = Some(CachedOutput {
+			input_hash,
+			output_hash: hash_bytes(&fs::read(output_path)?),
+		});
+		Ok(())
+	}
+}
+
+// Builds of the same records are byte-identical, the manifest lets teammates and CI compare them
+// It leaves out anything that depends on the machine or the time of the build
+pub fn write_build_manifest(output_path: &Path) -> anyhow::Result<()> {
+	let content = fs::read(output_path)?;
+	let manifest = serde_json::json!({
+		"spicy_version": env!("CARGO_PKG_VERSION"),
+		"plugin": output_path.file_name().unwrap_or_default().to_string_lossy(),
+		"bytes": content.len(),
+		"sha256": hash_bytes(&content),
+	});
+	let mut manifest_path = env::current_dir().unwrap();
+	manifest_path.push(BUILD_MANIFEST_FILE);
+	fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)? + "\n")?;
+	eprintln!("Build manifest written to {}", manifest_path.to_string_lossy());
+	Ok(())
+}
+
+pub fn hash_bytes(bytes: &[u8]) -> String {
+	format!("{:x}", Sha256::digest(bytes))
+}
+
+fn get_len_and_modified(path: &Path) -> anyhow::Result<(u64, u128)> {
+	let metadata = fs::metadata(path)?;
+	let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos();
+	Ok((metadata.len(), modified))
+}
+
+fn get_manifest_hash() -> String {
+	let mut path = env::current_dir().unwrap();
+	path.push(PROJECT_MANIFEST_FILE);
+	hash_bytes(&fs::read(path).unwrap_or_default())
+}
+
+fn get_cache_path() -> PathBuf {
+	let mut path = env::current_dir().unwrap();
+	path.push(BUILD_CACHE_FILE);
+	path
+}
@@ -1,5 +1,8 @@
 pub const TODD_UNIT: f32 = 69.5; // the scaling from meters to todd units -- openmw wiki says 70 but 69.5 seems more accurate?
 pub const ORIGINAL_FILE_PATH_JSON_ATTR: &str = "original_file_path";
+pub const PROJECT_MANIFEST_FILE: &str = "spicy.toml";
+// The format version written to the Header record of plugins
+pub const HEADER_VERSION: f64 = 1.3;
 
 pub const SQUARES_PER_CELL: usize = 64;
 
@@ -1,14 +1,15 @@
 use std::{
 	collections::HashSet,
 	env,
-	fs::{self, DirEntry},
-	path::PathBuf,
+	fs,
+	path::{Path, PathBuf},
 };
 
 use anyhow::anyhow;
 use gltf::Node;
+use rayon::prelude::*;
 
-use crate::constants::TODD_UNIT;
+use crate::{cache::BuildCache, constants::TODD_UNIT};
 
 #[derive(Default, Debug)]
 pub struct ObjScene {
@@ -18,7 +19,7 @@ pub struct ObjScene {
 #[derive(Default, Debug)]
 pub struct ObjMesh {
 	pub name: String,
-	pub children: Vec<Box<ObjMesh>>,
+	pub children: Vec<ObjMesh>,
 	pub vertices: Vec<[f32; 3]>,
 	pub normals: Vec<[f32; 3]>,
 	pub uvs: Vec<[f32; 2]>,
@@ -26,20 +27,19 @@ pub struct ObjMesh {
 	pub transform: [[f32; 4]; 4],
 }
 
-// one possible implementation of walking a directory only visiting files
-fn visit_dirs(
-	dir: &std::path::Path,
-	set: &mut HashSet<PathBuf>,
-	cb: &dyn Fn(&fs::DirEntry, &mut HashSet<PathBuf>) -> anyhow::Result<()>,
-) -> anyhow::Result<()> {
+// Collects all files in a directory and its subdirectories
+pub fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
 	if dir.is_dir() {
-		for entry in fs::read_dir(dir).unwrap() {
-			let entry = entry.unwrap();
-			let path = entry.path();
+		// Entries are sorted by name, read_dir order differs between file systems
+		let mut paths = fs::read_dir(dir)?
+			.map(|entry| entry.map(|entry| entry.path()))
+			.collect::<Result<Vec<_>, _>>()?;
+		paths.sort();
+		for path in paths {
 			if path.is_dir() {
-				visit_dirs(&path, set, cb).unwrap();
+				collect_files(&path, files)?;
 			} else {
-				cb(&entry, set).unwrap();
+				files.push(path);
 			}
 		}
 	}
@@ -92,9 +92,9 @@ fn convert_obj_list_to_dae_string(objects: &ObjScene) -> anyhow::Result<String>
 			.as_str(),
 		);
 		for v in &obj.vertices {
-			for i in 0..3 {
-				dae_string.push_str(" ");
-				let vv = v[i] * crate::constants::TODD_UNIT;
+			for component in v {
+				dae_string.push(' ');
+				let vv = component * crate::constants::TODD_UNIT;
 				dae_string.push_str(vv.to_string().as_str());
 			}
 		}
@@ -125,9 +125,9 @@ fn convert_obj_list_to_dae_string(objects: &ObjScene) -> anyhow::Result<String>
 			.as_str(),
 		);
 		for n in &obj.normals {
-			for i in 0..3 {
-				dae_string.push_str(" ");
-				dae_string.push_str(n[i].to_string().as_str());
+			for component in n {
+				dae_string.push(' ');
+				dae_string.push_str(component.to_string().as_str());
 			}
 		}
 		dae_string.push_str(
@@ -156,7 +156,7 @@ fn convert_obj_list_to_dae_string(objects: &ObjScene) -> anyhow::Result<String>
 			)
 			.as_str(),
 		);
-		if obj.uvs.len() == 0 {
+		if obj.uvs.is_empty() {
 			for _ in &obj.vertices {
 				dae_string.push_str(" 0 0");
 			}
@@ -164,9 +164,9 @@ fn convert_obj_list_to_dae_string(objects: &ObjScene) -> anyhow::Result<String>
 			return Err(anyhow!("Mismatching uv and vertex buffers found!"));
 		} else {
 			for t in &obj.uvs {
-				for i in 0..2 {
-					dae_string.push_str(" ");
-					dae_string.push_str(t[i].to_string().as_str());
+				for component in t {
+					dae_string.push(' ');
+					dae_string.push_str(component.to_string().as_str());
 				}
 			}
 		}
@@ -207,18 +207,15 @@ fn convert_obj_list_to_dae_string(objects: &ObjScene) -> anyhow::Result<String>
 			.as_str(),
 		); // in triangles count skipped this: `material="id-trimat-9"`
 		for i in &obj.triangles {
-			dae_string.push_str(" ");
+			dae_string.push(' ');
 			dae_string.push_str(i.to_string().as_str());
 		}
 		dae_string.push_str(
-			format!(
-				r###"
+			r###"
 				</p>
 			</triangles>
 		</mesh>
-	</geometry>"###
-			)
-			.as_str(),
+	</geometry>"###,
 		);
 	}
 	dae_string.push_str(
@@ -236,7 +233,7 @@ fn convert_obj_list_to_dae_string(objects: &ObjScene) -> anyhow::Result<String>
 				transform_string += " ";
 			}
 		}
-		println!("TRANSFORM: {:?}", transform_string);
+		eprintln!("TRANSFORM: {:?}", transform_string);
 		if obj.name == "collisionmesh" {
 			dae_string.push_str(
 				format!(
@@ -326,14 +323,13 @@ fn multiply_matrices(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
 fn walk_down_gltf(
 	node: &Node,
 	buffers: &Vec<gltf::buffer::Data>,
-	images: &Vec<gltf::image::Data>,
 	parent: Option<&mut ObjMesh>,
 	scene: &mut ObjScene,
 ) {
 	let mut this_node = ObjMesh::default();
 
 	for child in node.children() {
-		walk_down_gltf(&child, buffers, images, Some(&mut this_node), scene);
+		walk_down_gltf(&child, buffers, Some(&mut this_node), scene);
 	}
 
 	// TODO: transforms, material, name collisions, animations, bones, morph targets, uvs, handle non triangular primitives...
@@ -395,26 +391,26 @@ fn walk_down_gltf(
 			if first {
 				first = false;
 			} else {
-				this_node.children.push(Box::new(new_node_option.unwrap()));
+				this_node.children.push(new_node_option.unwrap());
 			}
 		}
 	}
 
 	// At the end, assign the new node to the scene roots or the parent
 	if let Some(parent) = parent {
-		parent.children.push(Box::new(this_node));
+		parent.children.push(this_node);
 	} else {
 		scene.roots.push(this_node);
 	}
 }
 
-fn read_meshes_from_glb(dir: &DirEntry) -> anyhow::Result<ObjScene> {
+fn read_meshes_from_glb(path: &Path) -> anyhow::Result<ObjScene> {
 	// gltf::r
-	let (gltf, buffers, images) = gltf::import(dir.path())?;
+	let (gltf, buffers, _images) = gltf::import(path)?;
 	let mut obj_scene = ObjScene { roots: vec![] };
 	for scene in gltf.scenes() {
 		for node in scene.nodes() {
-			walk_down_gltf(&node, &buffers, &images, None, &mut obj_scene);
+			walk_down_gltf(&node, &buffers, None, &mut obj_scene);
 		}
 	}
 
@@ -422,7 +418,7 @@ fn read_meshes_from_glb(dir: &DirEntry) -> anyhow::Result<ObjScene> {
 }
 
 // Replaces a part of a path string corresponding to the input directory with one corresponding to the target directory for assets
-pub fn get_target_path(original: &PathBuf) -> PathBuf {
+pub fn get_target_path(original: &Path) -> PathBuf {
 	let mut meshes_path = env::current_dir().unwrap();
 	meshes_path.push("assets");
 
@@ -430,43 +426,80 @@ pub fn get_target_path(original: &PathBuf) -> PathBuf {
 	meshes_target.push("build");
 
 	let original_str = original.to_str().unwrap();
-	return original_str
+	original_str
 		.replace(
 			meshes_path.to_str().unwrap(),
 			meshes_target.to_str().unwrap(),
 		)
-		.into();
+		.into()
+}
+
+// Checks whether an asset referenced by a record exists in the source assets, before any conversion
+// Meshes referenced as dae files may be produced from glb files with the same name
+pub fn asset_exists(subdir: &str, asset_path: &str) -> bool {
+	let mut path = std::env::current_dir().unwrap();
+	path.push(subdir);
+	path.push(asset_path);
+	if path.exists() {
+		return true;
+	}
+
+	if path.extension().unwrap_or_default() == "dae" {
+		path.set_extension("glb");
+		return path.exists();
+	}
+
+	false
+}
+
+// Converts a glb file into a dae file in the build directory
+fn convert_glb(file_path: &PathBuf) -> anyhow::Result<()> {
+	eprintln!("{:?}", file_path);
+	let objects = read_meshes_from_glb(file_path)
+		.map_err(|err| anyhow!("Failed to read {}: {}", file_path.to_string_lossy(), err))?;
+	let dae_string = convert_obj_list_to_dae_string(&objects)?;
+	let mut dae_path = file_path.clone();
+	dae_path.set_extension("dae");
+	fs::create_dir_all(get_target_path(&dae_path).parent().unwrap())?;
+	fs::write(get_target_path(&dae_path), dae_string)?;
+	Ok(())
 }
 
-fn copy_directory(subdir: &str) -> anyhow::Result<()> {
+// Copies a file to the build directory, unless the copy from the previous build is still valid
+fn copy_file(path: &Path, cache: &mut BuildCache) -> anyhow::Result<()> {
+	let target_path = get_target_path(path);
+	if cache.is_asset_fresh(path, &target_path) {
+		return Ok(());
+	}
+	let file_content = fs::read(path)?;
+	fs::create_dir_all(target_path.parent().unwrap())?;
+	fs::write(target_path, file_content)?;
+	cache.insert_asset(path)
+}
+
+fn copy_directory(subdir: &str, cache: &mut BuildCache) -> anyhow::Result<()> {
 	let mut dir_path = std::env::current_dir()?;
 	dir_path.push(subdir);
-	let mut all_icon_paths = std::collections::hash_set::HashSet::new();
-	visit_dirs(&dir_path, &mut all_icon_paths, &|dir, _| {
-		let file_content = fs::read(dir.path())?;
-		fs::create_dir_all(get_target_path(&dir.path()).parent().unwrap()).unwrap();
-		fs::write(get_target_path(&dir.path()), file_content).unwrap();
-		Ok(())
-	})?;
+	let mut files = vec![];
+	collect_files(&dir_path, &mut files)?;
+	for file in files {
+		copy_file(&file, cache)?;
+	}
 
 	Ok(())
 }
 
-pub fn compile_assets() -> anyhow::Result<()> {
-	copy_directory("assets/icons")?;
-	copy_directory("assets/textures")?;
-	copy_directory("assets/music")?;
-	copy_directory("assets/sound")?;
-	copy_directory("assets/scripts")?;
+pub fn compile_assets(cache: &mut BuildCache) -> anyhow::Result<()> {
+	copy_directory("assets/icons", cache)?;
+	copy_directory("assets/textures", cache)?;
+	copy_directory("assets/music", cache)?;
+	copy_directory("assets/sound", cache)?;
+	copy_directory("assets/scripts", cache)?;
 
 	let file_content = {
 		let mut out_script_path = std::env::current_dir()?;
 		out_script_path.push("assets/out.omwscripts");
-		if let Ok(e) = fs::read(out_script_path) {
-			Some(e)
-		} else {
-			None
-		}
+		fs::read(out_script_path).ok()
 	};
 	if let Some(file_content) = file_content {
 		let mut out_script_path = std::env::current_dir()?;
@@ -477,35 +510,44 @@ pub fn compile_assets() -> anyhow::Result<()> {
 
 	let mut all_mesh_paths = std::env::current_dir()?;
 	all_mesh_paths.push("assets/meshes");
-	let mut all_paths = std::collections::hash_set::HashSet::new();
-	println!("Mesh path: {:?}", all_mesh_paths);
-	visit_dirs(&all_mesh_paths, &mut all_paths, &|dir, all_paths| {
-		println!("{:?}", dir);
-		let dir_path = dir.path();
-		let extension = dir_path.extension().unwrap_or_default();
+	let mut all_paths = HashSet::new();
+	eprintln!("Mesh path: {:?}", all_mesh_paths);
+	let mut files = vec![];
+	collect_files(&all_mesh_paths, &mut files)?;
+	let mut skipped = 0;
+	let mut to_convert = vec![];
+	for file_path in files {
+		let extension = file_path.extension().unwrap_or_default();
 		if extension == "glb" {
 			// Dae files need to be preprocessed before being moved to another location
-			let objects = read_meshes_from_glb(dir).unwrap();
-			let dae_string = convert_obj_list_to_dae_string(&objects).unwrap();
-			let mut dae_path = dir_path.clone();
+			let mut dae_path = file_path.clone();
 			dae_path.set_extension("dae");
 			if !all_paths.insert(get_target_path(&dae_path)) {
-				return Err(anyhow!("Duplicate file: {}", dae_path.to_string_lossy()))?;
+				return Err(anyhow!("Duplicate file: {}", dae_path.to_string_lossy()));
 			}
-			fs::create_dir_all(get_target_path(&dae_path).parent().unwrap())?;
-			fs::write(get_target_path(&dae_path), dae_string).unwrap();
+			if cache.is_asset_fresh(&file_path, &get_target_path(&dae_path)) {
+				skipped += 1;
+				continue;
+			}
+			to_convert.push(file_path);
 		} else if extension == "dae" || extension == "txt" || extension == "osgt" {
 			// For dae/txt/osgt files, just copy them over
-			let file_content = fs::read(dir_path.clone())?;
-			let dae_path = dir_path.clone();
-			if !all_paths.insert(get_target_path(&dae_path)) {
-				return Err(anyhow!("Duplicate file: {}", dae_path.to_string_lossy()))?;
+			if !all_paths.insert(get_target_path(&file_path)) {
+				return Err(anyhow!("Duplicate file: {}", file_path.to_string_lossy()));
 			}
-			fs::create_dir_all(get_target_path(&dae_path).parent().unwrap())?;
-			fs::write(get_target_path(&dae_path), file_content).unwrap();
+			copy_file(&file_path, cache)?;
 		}
-		Ok(())
-	})?;
+	}
+
+	// Conversion is the slow part, so it runs on all cores
+	let results: Vec<anyhow::Result<()>> = to_convert.par_iter().map(convert_glb).collect();
+	for (file_path, result) in to_convert.iter().zip(results) {
+		result?;
+		cache.insert_asset(file_path)?;
+	}
+	if skipped > 0 {
+		eprintln!("{} meshes are up to date", skipped);
+	}
 
 	Ok(())
 }
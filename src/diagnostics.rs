@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+
+use colored::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{args::MessageFormat, constants::ORIGINAL_FILE_PATH_JSON_ATTR};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+	Error,
+	Warning,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+	pub severity: Severity,
+	// The record file the problem was found in
+	pub file: Option<String>,
+	pub record_id: Option<String>,
+	// JSON pointer to the offending field, relative to the record file
+	pub field: Option<String>,
+	pub message: String,
+}
+
+impl Severity {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Severity::Error => "error",
+			Severity::Warning => "warning",
+		}
+	}
+}
+
+impl Diagnostic {
+	pub fn to_json(&self) -> Value {
+		serde_json::json!({
+			"severity": self.severity.as_str(),
+			"file": self.file,
+			"record_id": self.record_id,
+			"pointer": self.field.clone().unwrap_or_default(),
+			"message": self.message,
+		})
+	}
+}
+
+// Collects problems found while processing records so that all of them can be reported at once
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+	pub items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+	pub fn new() -> Self {
+		Diagnostics::default()
+	}
+
+	pub fn error(&mut self, record: &Value, field: Option<&str>, message: impl Into<String>) {
+		self.push_for_record(Severity::Error, record, field, message.into());
+	}
+
+	pub fn warning(&mut self, record: &Value, field: Option<&str>, message: impl Into<String>) {
+		self.push_for_record(Severity::Warning, record, field, message.into());
+	}
+
+	// Reports a problem with a file that couldn't be read as a record
+	pub fn file_error(&mut self, file: &str, message: impl Into<String>) {
+		self.items.push(Diagnostic {
+			severity: Severity::Error,
+			file: Some(file.to_string()),
+			record_id: None,
+			field: None,
+			message: message.into(),
+		});
+	}
+
+	fn push_for_record(
+		&mut self,
+		severity: Severity,
+		record: &Value,
+		field: Option<&str>,
+		message: String,
+	) {
+		self.items.push(Diagnostic {
+			severity,
+			file: record
+				.get(ORIGINAL_FILE_PATH_JSON_ATTR)
+				.and_then(|path| path.as_str())
+				.map(|path| path.to_string()),
+			record_id: get_record_name(record),
+			field: field.map(|field| field.to_string()),
+			message,
+		});
+	}
+
+	pub fn append(&mut self, other: Diagnostics) {
+		self.items.extend(other.items);
+	}
+
+	// Moves diagnostics of a nested record (like a dialogue info) into this collection,
+	// prefixing their fields with the location of the nested record in the parent's file
+	pub fn append_nested(&mut self, nested: Diagnostics, parent: &Value, prefix: &str) {
+		let parent_file = parent
+			.get(ORIGINAL_FILE_PATH_JSON_ATTR)
+			.and_then(|path| path.as_str())
+			.map(|path| path.to_string());
+		for mut diagnostic in nested.items {
+			diagnostic.field = Some(format!(
+				"{}{}",
+				prefix,
+				diagnostic.field.unwrap_or_default()
+			));
+			if diagnostic.file.is_none() {
+				diagnostic.file = parent_file.clone();
+			}
+			if diagnostic.record_id.is_none() {
+				diagnostic.record_id = get_record_name(parent);
+			}
+			self.items.push(diagnostic);
+		}
+	}
+
+	pub fn error_count(&self) -> usize {
+		self.items
+			.iter()
+			.filter(|diagnostic| diagnostic.severity == Severity::Error)
+			.count()
+	}
+
+	pub fn warning_count(&self) -> usize {
+		self.items
+			.iter()
+			.filter(|diagnostic| diagnostic.severity == Severity::Warning)
+			.count()
+	}
+
+	pub fn has_errors(&self) -> bool {
+		self.error_count() > 0
+	}
+
+	pub fn report(&self, message_format: MessageFormat) {
+		match message_format {
+			MessageFormat::Human => self.print_summary(),
+			MessageFormat::Json => self.print_json(),
+		}
+	}
+
+	// Prints one JSON object per line for every diagnostic
+	pub fn print_json(&self) {
+		for diagnostic in &self.items {
+			println!("{}", diagnostic.to_json());
+		}
+	}
+
+	// Prints all diagnostics grouped by the file they originate from
+	pub fn print_summary(&self) {
+		if self.items.is_empty() {
+			return;
+		}
+
+		let mut by_file: BTreeMap<String, Vec<&Diagnostic>> = BTreeMap::new();
+		for diagnostic in &self.items {
+			by_file
+				.entry(
+					diagnostic
+						.file
+						.clone()
+						.unwrap_or_else(|| "<no file>".to_string()),
+				)
+				.or_default()
+				.push(diagnostic);
+		}
+
+		println!();
+		for (file, diagnostics) in by_file {
+			println!("{}", file.bold());
+			for diagnostic in diagnostics {
+				let severity = match diagnostic.severity {
+					Severity::Error => diagnostic.severity.as_str().red(),
+					Severity::Warning => diagnostic.severity.as_str().yellow(),
+				};
+				let mut location = String::new();
+				if let Some(record_id) = &diagnostic.record_id {
+					location.push_str(&format!(" [{}]", record_id));
+				}
+				if let Some(field) = &diagnostic.field {
+					location.push_str(&format!(" {}", field));
+				}
+				println!("  {}{}: {}", severity, location, diagnostic.message);
+			}
+		}
+		println!(
+			"\n{} errors, {} warnings",
+			self.error_count(),
+			self.warning_count()
+		);
+	}
+}
+
+// A human readable identifier of a record, used in diagnostics
+fn get_record_name(record: &Value) -> Option<String> {
+	for attribute in ["id", "skill_id", "effect_id", "name"] {
+		if let Some(value) = record.get(attribute).and_then(|value| value.as_str()) {
+			if !value.is_empty() {
+				return Some(value.to_string());
+			}
+		}
+	}
+
+	None
+}
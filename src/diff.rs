@@ -0,0 +1,299 @@
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	env,
+	path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use colored::*;
+use serde_json::Value;
+
+use crate::{
+	args::MessageFormat, build_header, constants::ORIGINAL_FILE_PATH_JSON_ATTR, count_plugin_records,
+	diagnostics::Diagnostics, ensure_tes3conv_exists, project::Project, read_plugin_records,
+	read_record_files, read_string_from_record, records, utils::create_subdirectory,
+};
+
+// Longer values, like base64 encoded landscape data, are shortened when printed
+const MAX_VALUE_LENGTH: usize = 80;
+
+// Records keyed by their type and id
+type RecordSet = BTreeMap<(String, String), Value>;
+
+struct FieldChange {
+	pointer: String,
+	old: Option<Value>,
+	new: Option<Value>,
+}
+
+// Compares two plugins or two record directories record by record
+pub fn diff(
+	project: &Project,
+	old_path: &str,
+	new_path: &str,
+	message_format: MessageFormat,
+) -> anyhow::Result<()> {
+	let mut old_records = load_records(project, Path::new(old_path), "diff_old")?;
+	let mut new_records = load_records(project, Path::new(new_path), "diff_new")?;
+	// Project directories other than the current one have no Header to compare with
+	let is_header = |(record_type, _): &(String, String)| record_type == "Header";
+	if old_records.keys().any(is_header) != new_records.keys().any(is_header) {
+		old_records.retain(|key, _| !is_header(key));
+		new_records.retain(|key, _| !is_header(key));
+	}
+
+	let mut record_types: BTreeSet<&String> = BTreeSet::new();
+	record_types.extend(old_records.keys().map(|(record_type, _)| record_type));
+	record_types.extend(new_records.keys().map(|(record_type, _)| record_type));
+
+	let (mut added, mut removed, mut changed) = (0, 0, 0);
+	for record_type in record_types {
+		let mut keys: BTreeSet<&String> = BTreeSet::new();
+		for records in [&old_records, &new_records] {
+			keys.extend(
+				records
+					.keys()
+					.filter(|(key_type, _)| key_type == record_type)
+					.map(|(_, key)| key),
+			);
+		}
+
+		let mut printed_type = false;
+		for key in keys {
+			let full_key = (record_type.clone(), key.clone());
+			let (change, fields) = match (old_records.get(&full_key), new_records.get(&full_key)) {
+				(None, Some(_)) => {
+					added += 1;
+					("added", vec![])
+				}
+				(Some(_), None) => {
+					removed += 1;
+					("removed", vec![])
+				}
+				(Some(old), Some(new)) => {
+					let mut fields = vec![];
+					diff_values(Some(old), Some(new), String::new(), &mut fields);
+					if fields.is_empty() {
+						continue;
+					}
+					changed += 1;
+					("changed", fields)
+				}
+				(None, None) => unreachable!(),
+			};
+			// Keys are lowercase, the id is shown the way the newer record spells it
+			let record = new_records.get(&full_key).or(old_records.get(&full_key));
+			let key = &record
+				.and_then(records::get_record_key)
+				.filter(|id| records::normalize_id(id) == *key)
+				.unwrap_or_else(|| key.clone());
+
+			match message_format {
+				MessageFormat::Human => {
+					if !printed_type {
+						println!("\n{}", record_type.bold());
+						printed_type = true;
+					}
+					print_change(change, key, &fields);
+				}
+				MessageFormat::Json => print_change_json(change, record_type, key, &fields),
+			}
+		}
+	}
+
+	if message_format == MessageFormat::Human {
+		println!(
+			"\n{} added, {} removed, {} changed",
+			added, removed, changed
+		);
+	}
+
+	Ok(())
+}
+
+// Reads records from a plugin, a project directory or a common/data directory
+fn load_records(project: &Project, path: &Path, temp_name: &str) -> anyhow::Result<RecordSet> {
+	if !path.exists() {
+		return Err(anyhow!("{} does not exist", path.to_string_lossy()));
+	}
+
+	let records: Vec<Value> = if path.is_dir() {
+		let mut data_path = PathBuf::from(path);
+		data_path.push("common/data");
+		if !data_path.exists() {
+			data_path = PathBuf::from(path);
+		}
+		// The Header is derived from spicy.toml, files left from older versions of spicy are ignored
+		let mut records: Vec<Value> = read_record_files(&data_path)?
+			.into_iter()
+			.map(|(_, record)| record)
+			.filter(|record| read_string_from_record(record, "type").unwrap_or_default() != "Header")
+			.collect();
+		if is_current_project(path) {
+			records.push(build_project_header(project, &records));
+		}
+		records
+	} else {
+		let extension = path
+			.extension()
+			.unwrap_or_default()
+			.to_string_lossy()
+			.to_lowercase();
+		if !["omwgame", "omwaddon", "esm", "esp"].contains(&extension.as_str()) {
+			return Err(anyhow!(
+				"{} is not a plugin or a directory",
+				path.to_string_lossy()
+			));
+		}
+		create_subdirectory("cache")?;
+		ensure_tes3conv_exists(None)?;
+		read_plugin_records(project, path.to_path_buf(), temp_name, false)?
+	};
+
+	let mut record_set = RecordSet::new();
+	for record in records {
+		let record_type = read_string_from_record(&record, "type").unwrap_or_default();
+		// The header has no id, there is only one per plugin
+		// Ids that only differ in case are the same record to the engine
+		let key = records::get_record_key(&record)
+			.map(|key| records::normalize_id(&key))
+			.unwrap_or_else(|| record_type.clone());
+		// Records with the same key are told apart by their order
+		let mut unique_key = key.clone();
+		let mut counter = 1;
+		while record_set.contains_key(&(record_type.clone(), unique_key.clone())) {
+			counter += 1;
+			unique_key = format!("{}#{}", key, counter);
+		}
+		record_set.insert((record_type, unique_key), record);
+	}
+
+	Ok(record_set)
+}
+
+// Whether the directory is the project spicy runs in, or its common/data directory
+fn is_current_project(path: &Path) -> bool {
+	let Ok(project_path) = env::current_dir().and_then(|path| path.canonicalize()) else {
+		return false;
+	};
+	let Ok(path) = path.canonicalize() else {
+		return false;
+	};
+	path == project_path || path == project_path.join("common/data")
+}
+
+// The Header compile builds for the records, problems with it are left to compile
+fn build_project_header(project: &Project, records: &[Value]) -> Value {
+	let mut header = build_header(project, count_plugin_records(records), &mut Diagnostics::new());
+	header
+		.as_object_mut()
+		.unwrap()
+		.remove(ORIGINAL_FILE_PATH_JSON_ATTR);
+	header
+}
+
+// Collects changed fields as JSON pointers
+fn diff_values(
+	old: Option<&Value>,
+	new: Option<&Value>,
+	pointer: String,
+	changes: &mut Vec<FieldChange>,
+) {
+	match (old, new) {
+		(Some(Value::Object(old_object)), Some(Value::Object(new_object))) => {
+			let keys: BTreeSet<&String> = old_object.keys().chain(new_object.keys()).collect();
+			for key in keys {
+				diff_values(
+					old_object.get(key),
+					new_object.get(key),
+					format!("{}/{}", pointer, escape_pointer_token(key)),
+					changes,
+				);
+			}
+		}
+		(Some(Value::Array(old_array)), Some(Value::Array(new_array))) => {
+			for index in 0..old_array.len().max(new_array.len()) {
+				diff_values(
+					old_array.get(index),
+					new_array.get(index),
+					format!("{}/{}", pointer, index),
+					changes,
+				);
+			}
+		}
+		(Some(old), Some(new)) if values_equal(old, new) => {}
+		(None, None) => {}
+		(old, new) => changes.push(FieldChange {
+			pointer,
+			old: old.cloned(),
+			new: new.cloned(),
+		}),
+	}
+}
+
+// Plugins store floats with single precision, so numbers are compared the same way
+fn values_equal(old: &Value, new: &Value) -> bool {
+	match (old, new) {
+		(Value::Number(old), Value::Number(new)) if old.is_f64() || new.is_f64() => {
+			old.as_f64().map(|value| value as f32) == new.as_f64().map(|value| value as f32)
+		}
+		_ => old == new,
+	}
+}
+
+fn escape_pointer_token(token: &str) -> String {
+	token.replace('~', "~0").replace('/', "~1")
+}
+
+fn format_value(value: &Value) -> String {
+	let formatted = value.to_string();
+	if formatted.chars().count() <= MAX_VALUE_LENGTH {
+		return formatted;
+	}
+	let shortened: String = formatted.chars().take(MAX_VALUE_LENGTH - 3).collect();
+	format!("{}...", shortened)
+}
+
+fn print_change(change: &str, key: &str, fields: &[FieldChange]) {
+	match change {
+		"added" => println!("  {} {}", "+".green(), key),
+		"removed" => println!("  {} {}", "-".red(), key),
+		_ => {
+			println!("  {} {}", "~".yellow(), key);
+			for field in fields {
+				let description = match (&field.old, &field.new) {
+					(Some(old), Some(new)) => {
+						format!("{} -> {}", format_value(old), format_value(new))
+					}
+					(None, Some(new)) => format!("{} {}", "+".green(), format_value(new)),
+					(Some(old), None) => format!("{} {}", "-".red(), format_value(old)),
+					(None, None) => unreachable!(),
+				};
+				println!("      {}: {}", field.pointer, description);
+			}
+		}
+	}
+}
+
+// One JSON object per added or removed record and per changed field
+fn print_change_json(change: &str, record_type: &str, key: &str, fields: &[FieldChange]) {
+	if fields.is_empty() {
+		println!(
+			"{}",
+			serde_json::json!({ "change": change, "type": record_type, "id": key })
+		);
+	}
+	for field in fields {
+		println!(
+			"{}",
+			serde_json::json!({
+				"change": change,
+				"type": record_type,
+				"id": key,
+				"pointer": field.pointer,
+				"old": field.old,
+				"new": field.new,
+			})
+		);
+	}
+}
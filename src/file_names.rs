@@ -0,0 +1,66 @@
+// Record files are named after their ids, but ids can hold characters that aren't allowed
+// in file names on every platform. Those characters are written as %XX, where XX are the hex
+// digits of each UTF-8 byte, the same way urls do it. `%` itself is escaped too, so every
+// id maps to exactly one file name and back.
+
+// Characters that can't be used in file names on windows, along with the escape character
+const RESERVED_CHARACTERS: [char; 10] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|', '%'];
+// Windows doesn't allow these as file names, no matter the extension
+const RESERVED_NAMES: [&str; 22] = [
+	"con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+	"com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+// Turns an id into a file name, without the extension
+// With `escape_uppercase`, uppercase letters are escaped too. It tells apart ids that only
+// differ in case on case insensitive file systems, like `Chair` and `chair`.
+pub fn escape_file_name(id: &str, escape_uppercase: bool) -> String {
+	// Trailing dots and spaces are dropped by windows
+	let kept_length = id.trim_end_matches(['.', ' ']).len();
+	let first_segment = id.split('.').next().unwrap_or_default().to_lowercase();
+	let is_reserved_name = RESERVED_NAMES.contains(&first_segment.as_str());
+
+	let mut escaped = String::new();
+	for (index, c) in id.char_indices() {
+		let must_escape = RESERVED_CHARACTERS.contains(&c)
+			|| c.is_control()
+			|| index >= kept_length
+			|| (index == 0 && is_reserved_name)
+			|| (escape_uppercase && c.is_uppercase());
+		if must_escape {
+			let mut bytes = [0; 4];
+			for byte in c.encode_utf8(&mut bytes).bytes() {
+				escaped.push_str(&format!("%{:02X}", byte));
+			}
+		} else {
+			escaped.push(c);
+		}
+	}
+	escaped
+}
+
+// Turns a file name made by `escape_file_name` back into the id
+pub fn unescape_file_name(file_name: &str) -> String {
+	let bytes = file_name.as_bytes();
+	let mut unescaped = vec![];
+	let mut index = 0;
+	while index < bytes.len() {
+		let byte = match bytes.get(index + 1..index + 3) {
+			Some(hex) if bytes[index] == b'%' => std::str::from_utf8(hex)
+				.ok()
+				.and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+			_ => None,
+		};
+		match byte {
+			Some(byte) => {
+				unescaped.push(byte);
+				index += 3;
+			}
+			None => {
+				unescaped.push(bytes[index]);
+				index += 1;
+			}
+		}
+	}
+	String::from_utf8_lossy(&unescaped).to_string()
+}
@@ -0,0 +1,95 @@
+use std::{env, fs};
+
+use anyhow::anyhow;
+use serde_json::{Number, Value};
+
+use crate::{args::MessageFormat, diagnostics::Diagnostics, process_directory};
+
+// The canonical form of a record file - keys in alphabetical order, two space indentation,
+// no trailing newline and floats written the way tes3conv reads them back
+// Everything spicy writes to common/data goes through here
+pub fn format_record(record: &Value) -> String {
+	serde_json::to_string_pretty(&normalize_floats(record)).unwrap()
+}
+
+// Records store single precision floats, anything more precise is lost on compile anyway
+// e.g. 0.30000001192092896 becomes 0.3
+fn normalize_floats(value: &Value) -> Value {
+	match value {
+		Value::Number(number) if number.is_f64() => {
+			let value = number.as_f64().unwrap() as f32;
+			let shortened: f64 = value.to_string().parse().unwrap();
+			Number::from_f64(shortened)
+				.map(Value::Number)
+				.unwrap_or_else(|| Value::Number(number.clone()))
+		}
+		Value::Array(array) => Value::Array(array.iter().map(normalize_floats).collect()),
+		Value::Object(object) => Value::Object(
+			object
+				.iter()
+				.map(|(key, value)| (key.clone(), normalize_floats(value)))
+				.collect(),
+		),
+		_ => value.clone(),
+	}
+}
+
+// Rewrites every record file in its canonical form, or only lists the files that aren't
+pub fn fmt(check: bool, message_format: MessageFormat) -> anyhow::Result<()> {
+	let mut data_path = env::current_dir().unwrap();
+	data_path.push("common/data");
+	let mut files = vec![];
+	process_directory(data_path, &mut files)?;
+	files.sort();
+
+	let mut diagnostics = Diagnostics::new();
+	let mut changed = 0;
+	for file in files {
+		let file_name = file.to_string_lossy().to_string();
+		let content = match fs::read_to_string(&file) {
+			Ok(content) => content,
+			Err(err) => {
+				diagnostics.file_error(&file_name, format!("Failed to read file: {}", err));
+				continue;
+			}
+		};
+		let record: Value = match serde_json::from_str(&content) {
+			Ok(record) => record,
+			Err(err) => {
+				diagnostics.file_error(&file_name, format!("Invalid JSON: {}", err));
+				continue;
+			}
+		};
+
+		let formatted = format_record(&record);
+		if formatted == content {
+			continue;
+		}
+		changed += 1;
+		if check {
+			diagnostics.file_error(&file_name, "File is not formatted, run `spicy fmt`.");
+		} else {
+			fs::write(&file, formatted)?;
+			if message_format == MessageFormat::Human {
+				println!("Formatted {}", file_name);
+			}
+		}
+	}
+
+	diagnostics.report(message_format);
+	if diagnostics.has_errors() {
+		return Err(anyhow!(
+			"{} files are not formatted or couldn't be read",
+			diagnostics.error_count()
+		));
+	}
+	if message_format == MessageFormat::Human {
+		if changed == 0 {
+			println!("All record files are formatted");
+		} else {
+			println!("Formatted {} files", changed);
+		}
+	}
+
+	Ok(())
+}
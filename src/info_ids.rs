@@ -0,0 +1,102 @@
+use std::{
+	collections::HashSet,
+	fs,
+	path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::{cache, format, process_directory, read_string_from_record};
+
+// Save games remember dialogue infos by their id, both the ones the player heard and the journal
+// entries they got. Infos keep their ids through compiles and decompiles, only new ones get a
+// fresh id, which is written into their record file once the plugin is built.
+
+// Ids of all dialogue infos that already have one
+pub fn collect_info_ids<'a>(records: impl IntoIterator<Item = &'a Value>) -> HashSet<String> {
+	let mut ids = HashSet::new();
+	for record in records {
+		for info in get_infos(record) {
+			if let Some(id) = get_info_id(info) {
+				ids.insert(id.to_string());
+			}
+		}
+	}
+	ids
+}
+
+// The id of an info, missing for infos that were added by hand
+pub fn get_info_id(info: &Value) -> Option<&str> {
+	info.get("id")
+		.and_then(|id| id.as_str())
+		.filter(|id| !id.is_empty())
+}
+
+// A numeric id like the ones the original game uses, derived from the dialogue and the info
+// The same info gets the same id, unless it is taken already
+pub fn generate_info_id(dialogue: &Value, index: usize, used_ids: &mut HashSet<String>) -> String {
+	let dialogue_id = read_string_from_record(dialogue, "id").unwrap_or_default();
+	let text = get_infos(dialogue)
+		.get(index)
+		.and_then(|info| info.get("text"))
+		.and_then(|text| text.as_str())
+		.unwrap_or_default();
+	let mut salt = 0;
+	loop {
+		let hash = cache::hash_bytes(format!("{}\n{}\n{}\n{}", dialogue_id, index, text, salt).as_bytes());
+		let id = u64::from_str_radix(&hash[..16], 16).unwrap().to_string();
+		if used_ids.insert(id.clone()) {
+			return id;
+		}
+		salt += 1;
+	}
+}
+
+// Gives every info in common/data that has no id a new one and saves it in its record file,
+// returns the files that were written
+// Files that can't be read are left to validation
+pub fn assign_missing_info_ids(data_path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+	let mut files = vec![];
+	process_directory(data_path.to_path_buf(), &mut files)?;
+	let dialogues: Vec<_> = files
+		.into_iter()
+		.filter_map(|file| {
+			let record: Value = serde_json::from_str(&fs::read_to_string(&file).ok()?).ok()?;
+			(read_string_from_record(&record, "type").ok()? == "Dialogue").then_some((file, record))
+		})
+		.collect();
+
+	let mut used_ids = collect_info_ids(dialogues.iter().map(|(_, record)| record));
+	let mut written = vec![];
+	for (file, mut dialogue) in dialogues {
+		let missing: Vec<usize> = get_infos(&dialogue)
+			.iter()
+			.enumerate()
+			.filter(|(_, info)| info.is_object() && get_info_id(info).is_none())
+			.map(|(index, _)| index)
+			.collect();
+		if missing.is_empty() {
+			continue;
+		}
+		for index in missing {
+			let id = generate_info_id(&dialogue, index, &mut used_ids);
+			dialogue["dialogue_infos"][index]
+				.as_object_mut()
+				.unwrap()
+				.insert("id".to_string(), id.into());
+		}
+		fs::write(&file, format::format_record(&dialogue))?;
+		eprintln!("Added dialogue info ids to {}", file.to_string_lossy());
+		written.push(file);
+	}
+
+	Ok(written)
+}
+
+fn get_infos(record: &Value) -> &[Value] {
+	record
+		.get("dialogue_infos")
+		.and_then(|infos| infos.as_array())
+		.map(|infos| infos.as_slice())
+		.unwrap_or_default()
+}
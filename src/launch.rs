@@ -0,0 +1,197 @@
+use std::{
+	env,
+	fs::File,
+	io::{BufRead, BufReader, Read, Write},
+	process::{Command, Stdio},
+	sync::{Arc, Mutex},
+	thread,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	args::{MessageFormat, RunArgs},
+	compile, ensure_openmw_exists, get_openmw_path,
+	project::Project,
+	utils::create_subdirectory,
+};
+
+// A named set of `spicy run` options, stored under [profiles.<name>] in spicy.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaunchProfile {
+	pub no_compile: bool,
+	pub menu: bool,
+	pub load: Option<String>,
+	pub start_cell: Option<String>,
+	// Passed to openmw as they are
+	pub args: Vec<String>,
+}
+
+impl LaunchProfile {
+	// Options given on the command line take precedence over the profile
+	fn merge(mut self, options: &RunArgs) -> Self {
+		self.no_compile |= options.no_compile;
+		// A start mode on the command line replaces the one of the profile
+		if options.menu || options.load.is_some() || options.start_cell.is_some() {
+			self.menu = options.menu;
+			self.load = options.load.clone();
+			self.start_cell = options.start_cell.clone();
+		}
+		self.args.extend(options.openmw_args.iter().cloned());
+		self
+	}
+
+	fn get_openmw_args(&self) -> anyhow::Result<Vec<String>> {
+		if self.menu && (self.load.is_some() || self.start_cell.is_some()) {
+			return Err(anyhow!(
+				"`menu` can't be combined with `load` or `start_cell`"
+			));
+		}
+		if self.load.is_some() && self.start_cell.is_some() {
+			return Err(anyhow!("`load` can't be combined with `start_cell`"));
+		}
+
+		let mut args = vec![];
+		if let Some(save) = &self.load {
+			args.extend(["--skip-menu".to_string(), "--load-savegame".to_string(), save.clone()]);
+		} else if !self.menu {
+			args.extend(["--skip-menu".to_string(), "--new-game".to_string()]);
+			if let Some(cell) = &self.start_cell {
+				args.extend(["--start".to_string(), cell.clone()]);
+			}
+		}
+		args.extend(self.args.iter().cloned());
+		Ok(args)
+	}
+}
+
+pub fn run(project: &Project, options: &RunArgs) -> anyhow::Result<()> {
+	let profile = match &options.profile {
+		Some(name) => project.profiles.get(name).cloned().ok_or_else(|| {
+			let names: Vec<&String> = project.profiles.keys().collect();
+			anyhow!("unknown launch profile `{}`, available profiles: {:?}", name, names)
+		})?,
+		None => LaunchProfile::default(),
+	};
+	let profile = profile.merge(options);
+	let openmw_args = profile.get_openmw_args()?;
+
+	if !profile.no_compile {
+		compile(project, MessageFormat::Human)?;
+	} else if !project.output_path().exists() {
+		return Err(anyhow!(
+			"{} doesn't exist, compile the project first",
+			project.output_path().to_string_lossy()
+		));
+	}
+
+	ensure_openmw_exists(None).unwrap();
+
+	println!("\n\n\n=== launching openmw ===\n\n\n");
+	let mut command = Command::new(get_openmw_path(project)?);
+	// Masters have to be loaded before the plugin itself
+	for master in project.master_paths() {
+		if let Some(directory) = master.parent() {
+			command.arg("--data").arg(directory);
+		}
+		command.arg("--content").arg(master.file_name().unwrap());
+	}
+	println!("Arguments: {:?}", openmw_args);
+	command
+		.arg("--content")
+		.arg(project.output_file_name())
+		.args(openmw_args);
+	run_with_log(command, "openmw")
+}
+
+// Runs a tool, streaming its output to the terminal and to a log file in cache/logs
+// Fails when the tool exits with an error or crashes
+pub fn run_with_log(mut command: Command, name: &str) -> anyhow::Result<()> {
+	create_subdirectory("cache/logs")?;
+	let timestamp = get_timestamp();
+	let mut log_path = env::current_dir().unwrap();
+	log_path.push(format!("cache/logs/{}-{}.log", name, timestamp));
+	// Runs started within the same second get their own logs
+	let mut counter = 1;
+	while log_path.exists() {
+		counter += 1;
+		log_path.set_file_name(format!("{}-{}-{}.log", name, timestamp, counter));
+	}
+	let log = Arc::new(Mutex::new(File::create(&log_path)?));
+
+	let mut child = command
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|err| anyhow!("failed to start {}: {}", name, err))?;
+	let stdout = child.stdout.take().unwrap();
+	let stderr = child.stderr.take().unwrap();
+	let stdout_thread = {
+		let log = log.clone();
+		thread::spawn(move || copy_lines(stdout, std::io::stdout(), log))
+	};
+	let stderr_thread = {
+		let log = log.clone();
+		thread::spawn(move || copy_lines(stderr, std::io::stderr(), log))
+	};
+	let status = child.wait()?;
+	stdout_thread.join().unwrap();
+	stderr_thread.join().unwrap();
+
+	let log_path = log_path.to_string_lossy();
+	if !status.success() {
+		let reason = match status.code() {
+			Some(code) => format!("exited with code {}", code),
+			None => "crashed".to_string(),
+		};
+		return Err(anyhow!("{} {}, see the log at {}", name, reason, log_path));
+	}
+	println!("{} log written to {}", name, log_path);
+
+	Ok(())
+}
+
+// Copies output line by line, so that lines from stdout and stderr don't mix in the log
+fn copy_lines(source: impl Read, mut terminal: impl Write, log: Arc<Mutex<File>>) {
+	let mut reader = BufReader::new(source);
+	let mut line = vec![];
+	while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+		let _ = terminal.write_all(&line);
+		let _ = terminal.flush();
+		let _ = log.lock().unwrap().write_all(&line);
+		line.clear();
+	}
+}
+
+// UTC time like 2024-05-17_14-03-59, so that logs sort by the time they were written
+fn get_timestamp() -> String {
+	let seconds = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_secs() as i64;
+	let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+	// Converts days since the unix epoch to a date in the gregorian calendar
+	let days = days + 719468;
+	let era = days.div_euclid(146097);
+	let day_of_era = days.rem_euclid(146097);
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let month_index = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+	let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+	format!(
+		"{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+		year,
+		month,
+		day,
+		time / 3600,
+		time % 3600 / 60,
+		time % 60
+	)
+}
@@ -1,40 +1,61 @@
 use crate::{
-	args::{Args, Commands},
-	constants::{ORIGINAL_FILE_PATH_JSON_ATTR, SQUARES_PER_CELL},
+	args::{Args, Commands, MessageFormat},
+	constants::{HEADER_VERSION, ORIGINAL_FILE_PATH_JSON_ATTR, PROJECT_MANIFEST_FILE, SQUARES_PER_CELL},
 	utils::create_subdirectory,
 };
-use anyhow::{anyhow, Ok};
+use anyhow::{anyhow, Context, Ok};
 use base64::Engine;
+use cache::{BuildCache, FileStamp};
 use clap::Parser;
 use colored::*;
-use dae::get_target_path;
+use diagnostics::{Diagnostic, Diagnostics};
+use project::Project;
+use rayon::prelude::*;
+use record_paths::RecordPaths;
+use reference_indices::ReferenceLedger;
 use records::get_record_types;
 use serde_json::{from_str, Value};
 use std::{
+	collections::{HashMap, HashSet},
 	env,
 	fs::{self, OpenOptions},
 	io::Write,
-	path::PathBuf,
+	path::{Path, PathBuf},
 	process::{Command, Stdio},
 };
-use utils::{copy_dir_from_res_to_game_dir, copy_file_from_res_to_game_dir, create_text_file};
+use utils::{copy_dir_from_res_to_game_dir, copy_file_from_res_to_game_dir};
 use world_gen::world::OpenmwWorld;
 
 mod args;
+mod backup;
+mod cache;
 mod constants;
 mod dae;
+mod diagnostics;
+mod diff;
+mod file_names;
+mod format;
+mod info_ids;
+mod launch;
+mod merge;
+mod project;
 mod record;
+mod record_paths;
 mod records;
+mod reference_indices;
+mod references;
+mod stats;
 mod utils;
+mod watch;
 mod world_gen;
 
 fn main() -> anyhow::Result<()> {
 	if let Err(e) = main_body() {
-		println!("{}", "Failed".red());
+		eprintln!("{}", "Failed".red());
 		return Err(e);
 	}
 
-	println!("{}", "Finished".green());
+	eprintln!("{}", "Finished".green());
 	Ok(())
 }
 
@@ -45,46 +66,105 @@ fn main_body() -> anyhow::Result<()> {
 		Commands::New { path } => {
 			new(path)?;
 		}
-		Commands::Run => {
-			run()?;
+		Commands::Run(options) => {
+			let project = Project::load()?;
+			launch::run(&project, &options)?;
 		}
 		Commands::Clear => {
+			Project::load()?;
 			ensure_common_exists()?;
 			clear()?;
 		}
 		Commands::Compile => {
+			let project = Project::load()?;
 			ensure_common_exists()?;
-			compile()?;
+			compile(&project, args.message_format)?;
+		}
+		Commands::Watch => {
+			let project = Project::load()?;
+			ensure_common_exists()?;
+			watch::watch(&project, args.message_format)?;
+		}
+		Commands::Check => {
+			let project = Project::load()?;
+			check(&project, args.message_format)?;
+		}
+		Commands::Add { record_type, id } => {
+			Project::load()?;
+			add(&record_type, &id)?;
+		}
+		Commands::RenameId {
+			old_id,
+			new_id,
+			record_type,
+		} => {
+			Project::load()?;
+			ensure_common_exists()?;
+			references::rename_id(&old_id, &new_id, record_type.as_deref())?;
+		}
+		Commands::Refs { id, record_type } => {
+			Project::load()?;
+			ensure_common_exists()?;
+			references::print_usages(&id, record_type.as_deref(), args.message_format)?;
+		}
+		Commands::Stats => {
+			let project = Project::load()?;
+			ensure_common_exists()?;
+			stats::stats(&project, args.message_format)?;
+		}
+		Commands::Fmt { check } => {
+			Project::load()?;
+			ensure_common_exists()?;
+			format::fmt(check, args.message_format)?;
+		}
+		Commands::MergeDriver { base, ours, theirs } => {
+			merge::merge_driver(&base, &ours, &theirs)?;
+		}
+		Commands::Diff { old, new } => {
+			let project = Project::load()?;
+			diff::diff(&project, &old, &new, args.message_format)?;
 		}
 		Commands::Decompile { input_path } => {
+			let project = Project::load()?;
 			ensure_common_exists()?;
-			decompile(input_path)?;
+			decompile(&project, input_path)?;
+		}
+		Commands::Undo => {
+			Project::load()?;
+			backup::undo()?;
 		}
 		Commands::Edit => {
-			edit()?;
+			let project = Project::load()?;
+			edit(&project)?;
 		}
 	}
 
 	Ok(())
 }
 
-fn check_for_spicy_toml() -> anyhow::Result<()> {
-	let mut path = env::current_dir().unwrap();
-	path.push("spicy.toml");
-	if path.exists() {
-		Ok(())
-	} else {
-		Err(anyhow!(
-			"could not find `spicy.toml` in `{}`",
-			path.to_string_lossy()
-		))
+// Resolves a tool path from the [tools] table of spicy.toml against the project directory
+fn get_tool_override(path: &Path, setting: &str) -> anyhow::Result<PathBuf> {
+	let mut tool_path = env::current_dir().unwrap();
+	tool_path.push(path);
+	if !tool_path.exists() {
+		return Err(anyhow!(
+			"`tools.{}` in {} points to {}, which does not exist",
+			setting,
+			PROJECT_MANIFEST_FILE,
+			tool_path.to_string_lossy()
+		));
 	}
+	Ok(tool_path)
 }
 
-fn get_tes3conv_path() -> PathBuf {
+fn get_tes3conv_path(project: &Project) -> anyhow::Result<PathBuf> {
+	if let Some(path) = project.tools.tes3conv.as_ref() {
+		return get_tool_override(path, "tes3conv");
+	}
+
 	// Parse tes3conv path
 	let mut tes3conv_path = env::current_dir().unwrap();
-	println!("Current dir: {:.?}", tes3conv_path);
+	eprintln!("Current dir: {:.?}", tes3conv_path);
 	if cfg!(target_os = "windows") {
 		tes3conv_path.push("bin/tes3conv/windows/tes3conv.exe");
 	} else if cfg!(target_os = "linux") {
@@ -99,13 +179,13 @@ fn get_tes3conv_path() -> PathBuf {
 		)
 	}
 
-	tes3conv_path
+	Ok(tes3conv_path)
 }
 
 fn get_openmw_root_path() -> PathBuf {
 	// Parse openmw path
 	let mut openmw_path = env::current_dir().unwrap();
-	println!("Current dir: {:.?}", openmw_path);
+	eprintln!("Current dir: {:.?}", openmw_path);
 	if cfg!(target_os = "windows") {
 		openmw_path.push("bin/openmw/windows");
 	} else if cfg!(target_os = "linux") {
@@ -123,7 +203,11 @@ fn get_openmw_root_path() -> PathBuf {
 	openmw_path
 }
 
-fn get_openmw_path() -> PathBuf {
+fn get_openmw_path(project: &Project) -> anyhow::Result<PathBuf> {
+	if let Some(path) = project.tools.openmw.as_ref() {
+		return get_tool_override(path, "openmw");
+	}
+
 	let mut openmw_path = get_openmw_root_path();
 	if cfg!(target_os = "windows") {
 		openmw_path.push("openmw.exe");
@@ -139,10 +223,14 @@ fn get_openmw_path() -> PathBuf {
 		)
 	}
 
-	openmw_path
+	Ok(openmw_path)
 }
 
-fn get_openmw_cs_path() -> PathBuf {
+fn get_openmw_cs_path(project: &Project) -> anyhow::Result<PathBuf> {
+	if let Some(path) = project.tools.openmw_cs.as_ref() {
+		return get_tool_override(path, "openmw_cs");
+	}
+
 	let mut openmw_path = get_openmw_root_path();
 	if cfg!(target_os = "windows") {
 		openmw_path.push("openmw-cs.exe");
@@ -158,7 +246,7 @@ fn get_openmw_cs_path() -> PathBuf {
 		)
 	}
 
-	openmw_path
+	Ok(openmw_path)
 }
 
 fn process_directory(mut input_path: PathBuf, outputs: &mut Vec<PathBuf>) -> anyhow::Result<()> {
@@ -166,10 +254,13 @@ fn process_directory(mut input_path: PathBuf, outputs: &mut Vec<PathBuf>) -> any
 		let new_input_path = input_path.clone().to_string_lossy().replace('/', "\\");
 		input_path = new_input_path.into();
 	}
-	let directories = fs::read_dir(input_path).unwrap();
-	for directory in directories {
-		let directory_entry = directory.unwrap();
-		let directory_path = directory_entry.path();
+	// Entries are sorted by name, read_dir order differs between file systems
+	let mut directory_paths: Vec<PathBuf> = fs::read_dir(input_path)
+		.unwrap()
+		.map(|directory| directory.unwrap().path())
+		.collect();
+	directory_paths.sort();
+	for directory_path in directory_paths {
 
 		if directory_path.is_dir() {
 			process_directory(directory_path.clone(), outputs).unwrap();
@@ -179,7 +270,6 @@ fn process_directory(mut input_path: PathBuf, outputs: &mut Vec<PathBuf>) -> any
 			if let Some(ext) = directory_path.extension() {
 				if ext == "json" {
 					outputs.push(directory_path.clone());
-					println!("{:?}", directory_path);
 				}
 			}
 		}
@@ -187,14 +277,31 @@ fn process_directory(mut input_path: PathBuf, outputs: &mut Vec<PathBuf>) -> any
 	Ok(())
 }
 
-fn ensure_common_exists() -> anyhow::Result<()> {
-	if !PathBuf::from("cache").exists()
-		|| !PathBuf::from("build").exists()
-		|| !PathBuf::from("common/data").exists()
-	{
-		clear()?;
+// Reads every record file in a data directory, sorted by path
+// Fails on the first broken file - callers rely on seeing every record
+fn read_record_files(data_path: &Path) -> anyhow::Result<Vec<(PathBuf, Value)>> {
+	let mut files = vec![];
+	process_directory(data_path.to_path_buf(), &mut files)?;
+	files.sort();
+
+	let mut records = vec![];
+	for file in files {
+		let content = fs::read_to_string(&file)
+			.with_context(|| format!("failed to read {}", file.to_string_lossy()))?;
+		let record: Value = from_str(&content)
+			.with_context(|| format!("failed to parse {}", file.to_string_lossy()))?;
+		records.push((file, record));
 	}
 
+	Ok(records)
+}
+
+fn ensure_common_exists() -> anyhow::Result<()> {
+	// Only the missing directories are created, the cache holds the backup `spicy undo` restores
+	create_subdirectory("cache")?;
+	create_subdirectory("build")?;
+	create_subdirectory("common/data")?;
+
 	let _ = ensure_tes3conv_exists(None);
 	let _ = ensure_openmw_exists(None);
 
@@ -242,44 +349,29 @@ fn ensure_tes3conv_exists(base_path: Option<String>) -> anyhow::Result<()> {
 	Ok(())
 }
 
-fn edit() -> anyhow::Result<()> {
-	compile()?;
+fn edit(project: &Project) -> anyhow::Result<()> {
+	compile(project, MessageFormat::Human)?;
 	ensure_openmw_exists(None).unwrap();
 
-	println!("\n\n\n=== launching openmw-cs ===\n\n\n");
-	let openmw_output = std::process::Command::new(get_openmw_cs_path()).output();
-	match openmw_output {
-		std::result::Result::Ok(output) => {
-			let formatted = format!("{:?}", output);
-			println!("{}", formatted.replace("\\n", "\n"));
-		}
-		Err(err) => return Err(anyhow!("{:?}", err)),
-	}
-
-	decompile(None)?;
-	Ok(())
-}
+	let plugin_hash = fs::read(project.output_path()).map(|content| cache::hash_bytes(&content))?;
 
-fn run() -> anyhow::Result<()> {
-	compile()?;
-
-	ensure_openmw_exists(None).unwrap();
+	println!("\n\n\n=== launching openmw-cs ===\n\n\n");
+	// The plugin could be half written after a crash, so it isn't decompiled
+	launch::run_with_log(Command::new(get_openmw_cs_path(project)?), "openmw-cs").map_err(|err| {
+		anyhow!(
+			"{}\nThe plugin wasn't decompiled, run `spicy decompile` if your changes were saved",
+			err
+		)
+	})?;
 
-	println!("\n\n\n=== launching openmw ===\n\n\n");
-	let openmw_output = std::process::Command::new(get_openmw_path())
-		.arg("--content")
-		.arg("out.omwgame")
-		.arg("--skip-menu")
-		.arg("--new-game")
-		.output();
-	match openmw_output {
-		std::result::Result::Ok(output) => {
-			let formatted = format!("{:?}", output);
-			println!("{}", formatted.replace("\\n", "\n"));
-		}
-		Err(err) => return Err(anyhow!("{:?}", err)),
+	// Nothing to bring back when openmw-cs was closed without saving
+	let new_plugin_hash = fs::read(project.output_path()).map(|content| cache::hash_bytes(&content))?;
+	if new_plugin_hash == plugin_hash {
+		println!("The plugin wasn't changed in openmw-cs, skipping decompile");
+		return Ok(());
 	}
 
+	decompile(project, None)?;
 	Ok(())
 }
 
@@ -293,9 +385,14 @@ fn new(relative_path: String) -> anyhow::Result<()> {
 		));
 	}
 	fs::create_dir_all(base_path.clone())?;
+	let project_name = base_path
+		.file_name()
+		.map(|name| name.to_string_lossy().to_string())
+		.unwrap_or_default();
+	let project = Project::new(&project_name);
 	let mut spicy_toml = base_path.clone();
-	spicy_toml.push("spicy.toml");
-	fs::write(spicy_toml, "# The project was created by spicy")?;
+	spicy_toml.push(constants::PROJECT_MANIFEST_FILE);
+	fs::write(spicy_toml, project.to_toml()?)?;
 
 	// Setup basic directories
 	ensure_tes3conv_exists(Some(relative_path.clone()))?;
@@ -309,6 +406,13 @@ fn new(relative_path: String) -> anyhow::Result<()> {
 		&Some(relative_path.clone()),
 	);
 
+	// Record files are merged with `spicy merge-driver`
+	copy_file_from_res_to_game_dir(
+		"res/template-for-gitattributes",
+		".gitattributes",
+		&Some(relative_path.clone()),
+	);
+
 	// Copy over template assets
 	copy_dir_from_res_to_game_dir(
 		"res/template-assets",
@@ -325,29 +429,80 @@ fn new(relative_path: String) -> anyhow::Result<()> {
 		Some(relative_path.clone()),
 	);
 
-	let json = r###"{
-  "author": "Author",
-  "description": "Spicy plugin for OpenMW",
-  "file_type": "Esm",
-  "flags": "",
-  "masters": [],
-  "type": "Header",
-  "version": 1.3
+	Ok(())
 }
-"###;
 
-	create_text_file(
-		format!("{}/common/data/Header", relative_path).as_str(),
-		"header.json",
-		json,
-	)
-	.unwrap();
+// Scaffolds a new record file that tes3conv accepts, refusing ids that are already taken
+fn add(record_type: &str, id: &str) -> anyhow::Result<()> {
+	// Accept the type in any case, but write it the way tes3conv expects
+	let record_type = get_record_types()
+		.into_iter()
+		.find(|known| known.eq_ignore_ascii_case(record_type))
+		.ok_or_else(|| anyhow!("unknown record type `{}`", record_type))?;
+	if id.is_empty() {
+		return Err(anyhow!("the id can't be empty"));
+	}
+	let mut record = records::get_record_template(&record_type, id)?;
+	let key = records::get_record_key(&record).unwrap();
+
+	let mut data_path = env::current_dir().unwrap();
+	data_path.push("common/data");
+	let mut files = vec![];
+	if data_path.exists() {
+		process_directory(data_path.clone(), &mut files)?;
+	}
+	for file in &files {
+		let Some(existing) = fs::read_to_string(file)
+			.ok()
+			.and_then(|content| from_str::<Value>(&content).ok())
+		else {
+			continue;
+		};
+		let existing_type = read_string_from_record(&existing, "type").unwrap_or_default();
+		if !references::share_namespace(&existing_type, &record_type) {
+			continue;
+		}
+		if let Some(existing_key) = records::get_record_key(&existing) {
+			if existing_key.eq_ignore_ascii_case(&key) {
+				return Err(anyhow!(
+					"`{}` is already defined in {}",
+					key,
+					file.to_string_lossy()
+				));
+			}
+		}
+	}
+
+	let mut file_path = data_path;
+	file_path.push(&record_type);
+	file_path.push(format!("{}.json", file_names::escape_file_name(&key, false)));
+	if file_path.exists() {
+		return Err(anyhow!(
+			"file {} already exists",
+			file_path.to_string_lossy()
+		));
+	}
+	fs::create_dir_all(file_path.parent().unwrap())?;
+	fs::write(&file_path, format::format_record(&record))?;
+	println!("Created {}", file_path.to_string_lossy());
+
+	// Point out what still has to be filled in by hand, like meshes and icons
+	record.as_object_mut().unwrap().insert(
+		ORIGINAL_FILE_PATH_JSON_ATTR.to_string(),
+		file_path.to_string_lossy().to_string().into(),
+	);
+	let mut diagnostics = Diagnostics::new();
+	validate_record_content(&record, &mut diagnostics);
+	validate_single_record(&record, &mut diagnostics);
+	if !diagnostics.items.is_empty() {
+		println!("Fill in these fields before compiling:");
+		diagnostics.print_summary();
+	}
 
 	Ok(())
 }
 
 fn clear() -> anyhow::Result<()> {
-	check_for_spicy_toml()?;
 	if PathBuf::from("cache").exists() {
 		fs::remove_dir_all("cache").unwrap();
 	}
@@ -358,26 +513,46 @@ fn clear() -> anyhow::Result<()> {
 	Ok(())
 }
 
-fn compile() -> anyhow::Result<()> {
-	check_for_spicy_toml()?;
+fn compile(project: &Project, message_format: MessageFormat) -> anyhow::Result<()> {
 	create_subdirectory("cache").unwrap();
 	create_subdirectory("build").unwrap();
 	ensure_tes3conv_exists(None).unwrap();
-	dae::compile_assets()?;
+	let mut cache = BuildCache::load();
+	let result = dae::compile_assets(&mut cache)
+		.and_then(|_| compile_records(project, message_format, &mut cache));
+	// Whatever was built before a failure doesn't need to be built again
+	cache.save()?;
 
-	let tes3conv_path = get_tes3conv_path();
+	result
+}
+
+// Validates the records and converts them into the plugin with tes3conv
+fn compile_records(
+	project: &Project,
+	message_format: MessageFormat,
+	cache: &mut BuildCache,
+) -> anyhow::Result<()> {
+	let tes3conv_path = get_tes3conv_path(project)?;
 
 	// Parse paths
-	let mut input_path = env::current_dir().unwrap();
-	input_path.push("common/data");
-	let mut output_path = env::current_dir().unwrap();
-	output_path.push("build/out.esm");
+	let final_path = project.output_path();
+	let mut output_path = final_path.clone();
+	// tes3conv decides on the file type based on the extension
+	output_path.set_extension(project.project.file_type.header_file_type().to_lowercase());
 	let mut temporary_json_path = env::current_dir().unwrap();
 	temporary_json_path.push("cache/temp.json");
-	println!("tes3conv path: {}", tes3conv_path.to_string_lossy());
-	println!("Output path: {}", output_path.to_string_lossy());
-	let mut final_path = output_path.clone();
-	final_path.set_extension("omwgame");
+	eprintln!("tes3conv path: {}", tes3conv_path.to_string_lossy());
+	eprintln!("Output path: {}", final_path.to_string_lossy());
+
+	let json = build_records(project, message_format, cache)?;
+
+	// Skip tes3conv if the plugin was already built from the same records
+	let input_hash = cache::hash_bytes(json.as_bytes());
+	if cache.is_output_fresh(&input_hash, &final_path) {
+		eprintln!("Output is up to date");
+		save_assigned_ids(project, &json, cache)?;
+		return cache::write_build_manifest(&final_path);
+	}
 
 	if final_path.exists() {
 		fs::remove_file(final_path.clone()).unwrap();
@@ -387,140 +562,367 @@ fn compile() -> anyhow::Result<()> {
 		fs::remove_file(output_path.clone()).unwrap();
 	}
 
+	eprintln!("Saving final json...");
+	let mut file = OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(true)
+		.open(temporary_json_path.clone())
+		.unwrap();
+	file.write_all(json.as_bytes()).unwrap();
+
+	eprintln!("Running: {:?}\n", tes3conv_path);
+	let output = Command::new(tes3conv_path)
+		.arg(temporary_json_path.to_string_lossy().to_string())
+		.arg(output_path.to_string_lossy().to_string())
+		// stdout is kept for diagnostics, tes3conv's own output goes with spicy's progress messages
+		.stdout(std::io::stderr())
+		.stderr(Stdio::inherit())
+		.output()
+		.expect("Failed to tes3conv");
+	eprintln!("{:?}", output);
+	if !output.status.success() || !output_path.exists() {
+		return Err(anyhow!("tes3conv failed with {}", output.status));
+	}
+
+	let converted = fs::read(output_path.clone()).unwrap();
+	fs::write(final_path.clone(), converted).unwrap();
+	fs::remove_file(output_path).unwrap(); // remove after copying
+	cache.set_output(input_hash, &final_path)?;
+	save_assigned_ids(project, &json, cache)?;
+
+	cache::write_build_manifest(&final_path)
+}
+
+// Saves the dialogue info ids and reference indices a successful build gave out, so the next one
+// uses them too
+// Both are given out in file order, so the files get the same ones as the plugin
+fn save_assigned_ids(project: &Project, json: &str, cache: &mut BuildCache) -> anyhow::Result<()> {
+	let mut data_path = env::current_dir().unwrap();
+	data_path.push("common/data");
+	let mut written = info_ids::assign_missing_info_ids(&data_path)?;
+	written.extend(reference_indices::assign_missing_reference_indices(project, &data_path)?);
+	// The cache takes the files as they are now, so watch doesn't build again for these writes
+	for file in written {
+		let loaded = load_record_file(&file, cache);
+		if let (Some(record), Some(stamp)) = (loaded.record, loaded.stamp) {
+			cache.insert_record(&file, stamp, record, loaded.diagnostics);
+		}
+	}
+
+	// Indices of this build can't be given to other references later
+	let records: Vec<Value> = from_str(json)?;
+	let mut reference_ledger = ReferenceLedger::load();
+	if reference_ledger.record(project, &records) {
+		reference_ledger.save()?;
+	}
+
+	Ok(())
+}
+
+// Runs everything compile does before tes3conv, without touching the build directory
+fn check(project: &Project, message_format: MessageFormat) -> anyhow::Result<()> {
+	let mut cache = BuildCache::load();
+	let result = build_records(project, message_format, &mut cache);
+	cache.save()?;
+	result?;
+	eprintln!("All records are valid");
+	Ok(())
+}
+
+// A record file after reading, parsing and checking its content
+struct LoadedRecord {
+	// Missing when the file couldn't be read as a record
+	record: Option<Value>,
+	diagnostics: Vec<Diagnostic>,
+	// Missing when the file couldn't be read
+	stamp: Option<FileStamp>,
+	from_cache: bool,
+}
+
+impl LoadedRecord {
+	fn failed(diagnostics: Diagnostics) -> Self {
+		LoadedRecord {
+			record: None,
+			diagnostics: diagnostics.items,
+			stamp: None,
+			from_cache: false,
+		}
+	}
+}
+
+// Loads a single record file, taking it from the cache if it didn't change
+// Runs on many threads at once, so it only reads the cache
+fn load_record_file(file: &Path, cache: &BuildCache) -> LoadedRecord {
+	// Unchanged files are taken from the cache, along with the problems found in them
+	if let Some((stamp, cached)) = cache.get_record(file) {
+		return LoadedRecord {
+			record: Some(cached.record.clone()),
+			diagnostics: cached.diagnostics.clone(),
+			stamp: Some(stamp),
+			from_cache: true,
+		};
+	}
+
+	eprintln!("Parsing: {:?}", file);
+	let mut diagnostics = Diagnostics::new();
+	let file_name = file.to_string_lossy().to_string();
+	let stamp = match FileStamp::read(file) {
+		std::result::Result::Ok(stamp) => stamp,
+		Err(err) => {
+			diagnostics.file_error(&file_name, format!("Failed to read the file: {}", err));
+			return LoadedRecord::failed(diagnostics);
+		}
+	};
+	let json_data = match fs::read_to_string(file) {
+		std::result::Result::Ok(json_data) => json_data,
+		Err(err) => {
+			diagnostics.file_error(&file_name, format!("Failed to read the file: {}", err));
+			return LoadedRecord::failed(diagnostics);
+		}
+	};
+	let mut parsed_json: Value = match from_str(&json_data) {
+		std::result::Result::Ok(parsed_json) => parsed_json,
+		Err(err) => {
+			diagnostics.file_error(&file_name, format!("Invalid JSON: {}", err));
+			return LoadedRecord::failed(diagnostics);
+		}
+	};
+	if !parsed_json.is_object() {
+		diagnostics.file_error(&file_name, "A record file must contain a json object");
+		return LoadedRecord::failed(diagnostics);
+	}
+	parsed_json
+		.as_object_mut()
+		.unwrap()
+		.insert(ORIGINAL_FILE_PATH_JSON_ATTR.to_string(), file_name.into());
+	if read_string_from_record(&parsed_json, "type").is_err() {
+		diagnostics.error(&parsed_json, Some("/type"), "A record has no type!");
+		return LoadedRecord::failed(diagnostics);
+	}
+
+	// Validate individual records...
+	validate_record_content(&parsed_json, &mut diagnostics);
+	LoadedRecord {
+		record: Some(parsed_json),
+		diagnostics: diagnostics.items,
+		stamp: Some(stamp),
+		from_cache: false,
+	}
+}
+
+// Reads, fills in and validates all records, returning the json that is fed to tes3conv
+fn build_records(
+	project: &Project,
+	message_format: MessageFormat,
+	cache: &mut BuildCache,
+) -> anyhow::Result<String> {
+	let mut input_path = env::current_dir().unwrap();
+	input_path.push("common/data");
+	if !input_path.exists() {
+		return Err(anyhow!(
+			"could not find record directory `{}`",
+			input_path.to_string_lossy()
+		));
+	}
+
 	let mut files = vec![];
 	process_directory(input_path, &mut files).unwrap();
-
-	let mut cell_reference_counter = 0;
-	let mut dialogue_info_id_counter = 0;
+	cache.forget_missing_records(&files);
+
+	// Read, parse and check the content of every file in parallel
+	let loaded_records: Vec<LoadedRecord> = files
+		.par_iter()
+		.map(|file| load_record_file(file, cache))
+		.collect();
+
+	// Filling in happens in file order, so the counters don't depend on thread timing
+	let mut diagnostics = Diagnostics::new();
+	let reference_ledger = ReferenceLedger::load();
+	let mut cell_reference_counter = reference_ledger.next_free_index(
+		project,
+		loaded_records
+			.iter()
+			.filter_map(|loaded| loaded.record.as_ref()),
+	) as usize;
 	let mut json = r"[".to_string();
 	let mut parsed_jsons = vec![];
-	for file in &files {
-		println!("Parsing: {:?}", file);
-		let json_data = fs::read_to_string(file).unwrap();
-		let mut parsed_json: Value = from_str(&json_data).expect("Invalid JSON");
-		parsed_json.as_object_mut().unwrap().insert(
-			ORIGINAL_FILE_PATH_JSON_ATTR.to_string(),
-			file.to_string_lossy().into(),
-		);
+	let mut record_diagnostics = vec![];
+	let mut cached_count = 0;
+	for (file, loaded) in files.iter().zip(loaded_records) {
+		let Some(mut parsed_json) = loaded.record else {
+			diagnostics.items.extend(loaded.diagnostics);
+			continue;
+		};
+		match loaded.stamp {
+			Some(stamp) if loaded.from_cache => {
+				cached_count += 1;
+				cache.update_record_stamp(file, stamp);
+			}
+			Some(stamp) => cache.insert_record(
+				file,
+				stamp,
+				parsed_json.clone(),
+				loaded.diagnostics.clone(),
+			),
+			None => {}
+		}
 
-		// Validate individual records...
-		fill_in_single_record(&mut parsed_json, files.len(), &mut cell_reference_counter).unwrap();
-		validate_single_record(&parsed_json).unwrap();
+		let mut single_diagnostics = Diagnostics::new();
+		single_diagnostics.items = loaded.diagnostics;
+		// The Header is derived from spicy.toml, files left from older versions of spicy are skipped
+		if read_string_from_record(&parsed_json, "type").unwrap_or_default() == "Header" {
+			single_diagnostics.warning(
+				&parsed_json,
+				None,
+				"The Header record is derived from spicy.toml, this file is ignored and can be deleted",
+			);
+			diagnostics.append(single_diagnostics);
+			continue;
+		}
+		fill_in_single_record(
+			project,
+			&mut parsed_json,
+			&mut cell_reference_counter,
+			&mut single_diagnostics,
+		);
 
 		parsed_jsons.push(parsed_json);
+		record_diagnostics.push(single_diagnostics);
 	}
+	if cached_count > 0 {
+		eprintln!("{} records are up to date", cached_count);
+	}
+
+	// Checks against the assets only read the file system, so they can run in parallel too
+	parsed_jsons
+		.par_iter()
+		.zip(record_diagnostics.par_iter_mut())
+		.for_each(|(parsed_json, single_diagnostics)| {
+			validate_single_record(parsed_json, single_diagnostics)
+		});
+	for single_diagnostics in record_diagnostics {
+		diagnostics.append(single_diagnostics);
+	}
+
 	// Validate all recorda at once
-	validate_records_together(&mut parsed_jsons).unwrap();
+	validate_records_together(&mut parsed_jsons, &mut diagnostics);
+	reference_indices::check_reference_indices(
+		project,
+		&parsed_jsons,
+		&reference_ledger,
+		&mut diagnostics,
+	);
 
-	// Write the final value
+	// Write the final value, starting with the header
+	let mut used_info_ids = info_ids::collect_info_ids(&parsed_jsons);
+	let record_count = parsed_jsons.len();
+	json.push_str(&build_header(project, count_plugin_records(&parsed_jsons), &mut diagnostics).to_string());
+	if record_count > 0 {
+		json.push_str(",\n");
+	}
 	for (index, parsed_json) in parsed_jsons.iter_mut().enumerate() {
 		let mut infos = vec![];
 		if read_string_from_record(parsed_json, "type").unwrap() == "Dialogue" {
-			infos = parsed_json
-				.get("dialogue_infos")
-				.unwrap()
-				.as_array()
-				.unwrap()
-				.clone();
+			match parsed_json.get("dialogue_infos").and_then(|infos| infos.as_array()) {
+				Some(dialogue_infos) => infos = dialogue_infos.clone(),
+				None => diagnostics.error(
+					parsed_json,
+					Some("/dialogue_infos"),
+					"A dialogue has no dialogue_infos array",
+				),
+			}
 			parsed_json.as_object_mut().unwrap().remove("dialogue_info");
 		}
 		json.push_str(&parsed_json.to_string());
 
 		// Preprocess infos
+		// Infos without an id, which `spicy check` doesn't save, get one for this build only
+		let ids: Vec<String> = (0..infos.len())
+			.map(|idx| match info_ids::get_info_id(&infos[idx]) {
+				Some(id) => id.to_string(),
+				None => info_ids::generate_info_id(parsed_json, idx, &mut used_info_ids),
+			})
+			.collect();
 		for idx in 0..infos.len() {
-			infos[idx].as_object_mut().unwrap().insert(
-				"id".to_string(),
-				dialogue_info_id_counter.to_string().into(),
-			);
-			if idx == 0 {
-				infos[idx]
-					.as_object_mut()
-					.unwrap()
-					.insert("prev_id".to_string(), "".into());
-			} else {
-				infos[idx].as_object_mut().unwrap().insert(
-					"prev_id".to_string(),
-					(dialogue_info_id_counter - 1).to_string().into(),
-				);
-			}
-			if idx == infos.len() - 1 {
-				infos[idx]
-					.as_object_mut()
-					.unwrap()
-					.insert("next_id".to_string(), "".into());
-			} else {
-				infos[idx].as_object_mut().unwrap().insert(
-					"next_id".to_string(),
-					(dialogue_info_id_counter + 1).to_string().into(),
-				);
-			}
-			dialogue_info_id_counter += 1;
+			let info = infos[idx].as_object_mut().unwrap();
+			info.insert("id".to_string(), ids[idx].clone().into());
+			let prev_id = if idx == 0 { "" } else { &ids[idx - 1] };
+			info.insert("prev_id".to_string(), prev_id.into());
+			let next_id = ids.get(idx + 1).map(|id| id.as_str()).unwrap_or_default();
+			info.insert("next_id".to_string(), next_id.into());
 		}
 
-		if read_string_from_record(parsed_json, "type").unwrap() == "Dialogue" {
-			for info in &mut infos {
-				fill_in_single_record(info, 0, &mut cell_reference_counter).unwrap();
-				validate_single_record(info).unwrap();
+		for (idx, info) in infos.iter_mut().enumerate() {
+			let mut info_diagnostics = Diagnostics::new();
+			fill_in_single_record(
+				project,
+				info,
+				&mut cell_reference_counter,
+				&mut info_diagnostics,
+			);
+			validate_record_content(info, &mut info_diagnostics);
+			validate_single_record(info, &mut info_diagnostics);
+			diagnostics.append_nested(
+				info_diagnostics,
+				parsed_json,
+				&format!("/dialogue_infos/{}", idx),
+			);
 
-				json.push_str(",\n");
-				json.push_str(&info.to_string());
-			}
+			json.push_str(",\n");
+			json.push_str(&info.to_string());
 		}
-		if index != files.len() - 1 {
+		if index != record_count - 1 {
 			json.push_str(",\n");
 		}
 	}
 	json.push(']');
 
-	validate_json(&from_str(&json).unwrap()).unwrap();
-
-	println!("Saving final json...");
-	let mut file = OpenOptions::new()
-		.write(true)
-		.create(true)
-		.truncate(true)
-		.open(temporary_json_path.clone())
-		.unwrap();
-	file.write_all(json.as_bytes()).unwrap();
-
-	println!("Running: {:?}\n", tes3conv_path);
-	let output = Command::new(tes3conv_path)
-		.arg(temporary_json_path.to_string_lossy().to_string())
-		.arg(output_path.to_string_lossy().to_string())
-		.stdout(Stdio::inherit())
-		.stderr(Stdio::inherit())
-		.output()
-		.expect("Failed to tes3conv");
+	validate_json(&from_str(&json).unwrap(), &mut diagnostics);
 
-	let converted = fs::read(output_path.clone()).unwrap();
-	fs::write(final_path, converted).unwrap();
-	fs::remove_file(output_path).unwrap(); // remove after copying
-
-	println!("{:?}", output);
+	diagnostics.report(message_format);
+	if diagnostics.has_errors() {
+		return Err(anyhow!(
+			"validation failed with {} errors",
+			diagnostics.error_count()
+		));
+	}
+	// Decompile writes the records back to the files they came from
+	RecordPaths::from_records(&parsed_jsons).save()?;
 
-	Ok(())
+	Ok(json)
 }
 
-fn decompile(input_path: Option<String>) -> anyhow::Result<()> {
-	check_for_spicy_toml()?;
-	let tes3conv_path = get_tes3conv_path();
+// Converts a plugin to json with tes3conv and brings the records into the form they are stored in
+// common/data, with dialogue infos embedded in their dialogues
+// `temp_name` names the intermediate files in the cache directory
+fn read_plugin_records(
+	project: &Project,
+	mut input_path: PathBuf,
+	temp_name: &str,
+	verbose: bool,
+) -> anyhow::Result<Vec<Value>> {
+	let tes3conv_path = get_tes3conv_path(project)?;
 
 	// Parse paths
-	let mut input_path = PathBuf::from(input_path.unwrap_or("build/out.omwgame".to_string()));
 	let mut output_path = env::current_dir().unwrap();
-	output_path.push("cache/temp.json");
-	println!("tes3conv path: {}", tes3conv_path.to_string_lossy());
-	println!("Input path: {}", input_path.to_string_lossy());
-	println!("Output path: {}", output_path.to_string_lossy());
+	output_path.push(format!("cache/{}.json", temp_name));
+	if verbose {
+		eprintln!("tes3conv path: {}", tes3conv_path.to_string_lossy());
+		eprintln!("Input path: {}", input_path.to_string_lossy());
+		eprintln!("Output path: {}", output_path.to_string_lossy());
+	}
 
 	if input_path.extension().unwrap_or_default() == "omwgame"
 		|| input_path.extension().unwrap_or_default() == "omwaddon"
 	{
-		println!("Working with openmw... Converting extension...");
+		if verbose {
+			eprintln!("Working with openmw... Converting extension...");
+		}
 		let data = fs::read(input_path.clone()).unwrap();
 
 		let mut new_input_path = env::current_dir().unwrap();
-		new_input_path.push("cache/temp.esm");
+		new_input_path.push(format!("cache/{}.esm", temp_name));
 
 		let mut file = OpenOptions::new()
 			.write(true)
@@ -545,26 +947,37 @@ fn decompile(input_path: Option<String>) -> anyhow::Result<()> {
 		fs::remove_file(output_path.clone()).unwrap();
 	}
 
-	println!("Running: {:?}\n", tes3conv_path);
+	if verbose {
+		eprintln!("Running: {:?}\n", tes3conv_path);
+	}
+	// Errors from tes3conv are always shown, its other output only when asked for
 	let output = Command::new(tes3conv_path)
 		.arg(input_path.to_string_lossy().to_string())
 		.arg(output_path.to_string_lossy().to_string())
-		.stdout(Stdio::inherit())
+		.stdout(if verbose {
+			std::io::stderr().into()
+		} else {
+			Stdio::null()
+		})
 		.stderr(Stdio::inherit())
 		.output()
 		.expect("Failed to tes3conv");
 
-	println!("{:?}", output);
+	if verbose {
+		eprintln!("{:?}", output);
+	}
+	if !output.status.success() || !output_path.exists() {
+		return Err(anyhow!(
+			"tes3conv failed to convert {}",
+			input_path.to_string_lossy()
+		));
+	}
 
 	// Read back json for validation // .unwrap().unwrap().unwrap() why would we validate on DECOMPILEs.unwrap()
 	let json_data = fs::read_to_string(output_path.clone()).unwrap();
 	let parsed_json: Value = from_str(&json_data).expect("Invalid JSON");
 	// validate_json(&parsed_json).unwrap();
 
-	create_record_dirs(None).unwrap();
-
-	// Create files for invividual record types and fill them with json
-	println!("Creating record files...");
 	let mut records = parsed_json.as_array().unwrap().clone();
 
 	// Update dialogue infos on dialogues
@@ -577,16 +990,18 @@ fn decompile(input_path: Option<String>) -> anyhow::Result<()> {
 			.unwrap()
 			.to_string();
 		if record_type == "Cell" {
-			// For cells, we want to remove the mast_index and ref_index because we strictly want to create new games, not mods!
-			// We can later make it an optional functionality instead, though.
+			// For cells, we remove the mast_index of our own references, it is always 0 for them.
+			// Their refr_index is kept, save games refer to references by it.
 			let refs = records[idx]
 				.get_mut("references")
 				.unwrap()
 				.as_array_mut()
 				.unwrap();
 			for re in refs {
+				if project.is_addon() && get_master_index(re) != 0 {
+					continue;
+				}
 				re.as_object_mut().unwrap().remove("mast_index");
-				re.as_object_mut().unwrap().remove("refr_index");
 			}
 		}
 		if record_type == "Dialogue" {
@@ -599,7 +1014,6 @@ fn decompile(input_path: Option<String>) -> anyhow::Result<()> {
 		}
 		if record_type == "DialogueInfo" {
 			let mut rec = records[idx].clone();
-			rec.as_object_mut().unwrap().remove("id").unwrap();
 			rec.as_object_mut().unwrap().remove("next_id").unwrap();
 			rec.as_object_mut().unwrap().remove("prev_id").unwrap();
 
@@ -628,37 +1042,87 @@ fn decompile(input_path: Option<String>) -> anyhow::Result<()> {
 		// }
 	}
 
-	let mut counter = 0;
-	let mut all_file_names = vec![];
-	for record in &records {
-		let record_type = record.get("type").unwrap().as_str().unwrap().to_string();
+	// At the end, delete the file as we won't need it anymore.
+	if output_path.clone().exists() {
+		fs::remove_file(output_path.clone()).unwrap();
+	}
 
-		if record_type == "DialogueInfo" {
-			continue; // skip dialogue infos
+	// Dialogue infos are embedded in their dialogues now
+	records.retain(|record| {
+		record
+			.get("type")
+			.and_then(|record_type| record_type.as_str())
+			!= Some("DialogueInfo")
+	});
+
+	Ok(records)
+}
+
+fn decompile(project: &Project, input_path: Option<String>) -> anyhow::Result<()> {
+	let input_path = input_path
+		.map(PathBuf::from)
+		.unwrap_or_else(|| project.output_path());
+	let mut records = read_plugin_records(project, input_path, "temp", true)?;
+	verify_decompiled_records(&records)?;
+	// The Header is derived from spicy.toml on compile, it doesn't get a file
+	records.retain(|record| read_string_from_record(record, "type").unwrap_or_default() != "Header");
+
+	// Records are written to a staging directory and only replace common/data once they are all there
+	let staging_path = backup::create_staging_dir()?;
+
+	// Records go back to the files they were compiled from, the rest get a file named after them
+	let record_paths = RecordPaths::load();
+	let mut used_paths = HashSet::new();
+	let target_paths: Vec<Option<PathBuf>> = records
+		.iter()
+		.map(|record| {
+			record_paths
+				.get(record)
+				.filter(|path| used_paths.insert(path.to_string_lossy().to_lowercase()))
+		})
+		.collect();
+
+	// Ids that only differ in case would end up in the same file on case insensitive file systems
+	let mut name_counts: HashMap<(String, String), usize> = HashMap::new();
+	for (record, target_path) in records.iter().zip(&target_paths) {
+		if target_path.is_none() {
+			let record_type = read_string_from_record(record, "type").unwrap_or_default();
+			let name = get_default_file_name(record).to_lowercase();
+			*name_counts.entry((record_type, name)).or_default() += 1;
 		}
-		let mut file_name = counter.to_string();
-		if let Some(id) = record.get("id") {
-			file_name = id.as_str().unwrap().to_string();
-		} else if let Some(name) = record.get("name") {
-			if !name.as_str().unwrap().is_empty() {
-				file_name = name.as_str().unwrap().to_string();
-			} else {
-				counter += 1;
-			}
-		} else {
-			counter += 1;
+	}
+
+	// Create files for invividual record types and fill them with json
+	println!("Creating record files...");
+	for (record, target_path) in records.iter().zip(target_paths) {
+		let record_type = record.get("type").unwrap().as_str().unwrap().to_string();
+		if let Some(target_path) = target_path {
+			let file_path = staging_path.join(target_path);
+			fs::create_dir_all(file_path.parent().unwrap())?;
+			fs::write(&file_path, format::format_record(record))?;
+			continue;
 		}
-		if all_file_names.contains(&file_name) {
-			println!("Duplicate: {}", file_name);
-			file_name.push('_');
-			file_name.push_str(&counter.to_string());
+
+		// Uppercase letters are escaped when the name is taken by a record with a differently cased id
+		let name = get_default_file_name(record);
+		let is_taken = |file_name: &str| {
+			used_paths.contains(&format!("{}/{}.json", record_type, file_name).to_lowercase())
+		};
+		let collides = name_counts[&(record_type.clone(), name.to_lowercase())] > 1
+			|| is_taken(&file_names::escape_file_name(&name, false));
+		let escaped_name = file_names::escape_file_name(&name, collides);
+		// Only records with exactly the same id are left, they are numbered in plugin order
+		let mut file_name = escaped_name.clone();
+		let mut counter = 1;
+		while is_taken(&file_name) {
+			println!("Duplicate: {}", name);
 			counter += 1;
+			file_name = format!("{}~{}", escaped_name, counter);
 		}
-		all_file_names.push(file_name.clone());
-		file_name.push_str(".json");
+		used_paths.insert(format!("{}/{}.json", record_type, file_name).to_lowercase());
+		let file_name = format!("{}.json", file_name);
 
-		let mut file_path = env::current_dir().unwrap();
-		file_path.push("common/data");
+		let mut file_path = staging_path.clone();
 		file_path.push(record_type);
 		file_path.push(file_name);
 
@@ -671,14 +1135,62 @@ fn decompile(input_path: Option<String>) -> anyhow::Result<()> {
 			println!();
 			return Err(anyhow!("Failed to create a file: {:?}", file_path));
 		}
-		let stringified_record = serde_json::to_string_pretty(record).unwrap();
+		let stringified_record = format::format_record(record);
 		let data_to_write = stringified_record.as_bytes();
 		file.unwrap().write_all(data_to_write).unwrap();
 	}
 
-	// At the end, delete the file as we won't need it anymore.
-	if output_path.clone().exists() {
-		fs::remove_file(output_path.clone()).unwrap();
+	// Every record has to be readable from its own file, files overwritten by records
+	// with clashing names would be lost otherwise
+	let written = read_record_files(&staging_path)?;
+	if written.len() != records.len() {
+		return Err(anyhow!(
+			"decompiled {} records, but only {} record files were written - common/data was left unchanged",
+			records.len(),
+			written.len()
+		));
+	}
+
+	backup::replace_data_dir(&staging_path)?;
+	println!(
+		"Decompiled {} records, `spicy undo` brings back the previous ones",
+		records.len()
+	);
+
+	Ok(())
+}
+
+// The name of a record file, before escaping - the id for most records, the grid for exterior cells
+fn get_default_file_name(record: &Value) -> String {
+	match records::get_record_key(record) {
+		Some(key) if !key.is_empty() => key,
+		_ => read_string_from_record(record, "type")
+			.unwrap_or_default()
+			.to_lowercase(),
+	}
+}
+
+// Makes sure tes3conv produced a whole plugin before anything in common/data is replaced
+fn verify_decompiled_records(records: &[Value]) -> anyhow::Result<()> {
+	let record_types = get_record_types();
+	let mut header_count = 0;
+	for record in records {
+		let record_type = read_string_from_record(record, "type").unwrap_or_default();
+		if !record_types.contains(&record_type) {
+			return Err(anyhow!(
+				"the plugin contains an unknown record type `{}`",
+				record_type
+			));
+		}
+		if record_type == "Header" {
+			header_count += 1;
+		}
+	}
+	if header_count != 1 {
+		return Err(anyhow!(
+			"the plugin has {} Header records instead of one",
+			header_count
+		));
 	}
 
 	Ok(())
@@ -687,8 +1199,9 @@ fn decompile(input_path: Option<String>) -> anyhow::Result<()> {
 fn create_record_dirs(base_path: Option<String>) -> anyhow::Result<()> {
 	// Once the json is validated, we can write it to individual directories
 	// Create directories for record types
+	// The Header is derived from spicy.toml, it has no directory
 	let record_types = get_record_types();
-	for record_type in record_types {
+	for record_type in record_types.iter().filter(|record_type| *record_type != "Header") {
 		let mut base_dir = env::current_dir().unwrap();
 		if let Some(base_path) = base_path.as_ref() {
 			base_dir.push(base_path);
@@ -704,58 +1217,86 @@ fn create_record_dirs(base_path: Option<String>) -> anyhow::Result<()> {
 	Ok(())
 }
 
-fn validate_json(parsed_json: &Value) -> anyhow::Result<()> {
-	if !parsed_json.is_array() {
-		return Err(anyhow!("The outtermost json object must be an array!"));
-	}
+fn validate_json(parsed_json: &Value, diagnostics: &mut Diagnostics) {
+	let Some(records) = parsed_json.as_array() else {
+		diagnostics.error(
+			parsed_json,
+			None,
+			"The outtermost json object must be an array!",
+		);
+		return;
+	};
 
 	let record_types = get_record_types();
-	let records = parsed_json.as_array().unwrap();
 
 	// Check for type presence and make sure all record types are known
+	// Individual records were already validated when they were read
 	for record in records {
-		let record_type = record.get("type");
-		if let Some(record_type) = record_type {
-			if !record_types.contains(&record_type.as_str().unwrap().to_string()) {
-				if record_type.as_str().unwrap() == "DialogueInfo" {
+		let record_type = read_string_from_record(record, "type");
+		if let anyhow::Result::Ok(record_type) = record_type {
+			if !record_types.contains(&record_type) {
+				if record_type == "DialogueInfo" {
 					// Dialogue infos need to be handled differently...
 				} else {
-					return Err(anyhow!("Unknown record type: {}", record_type));
+					diagnostics.error(
+						record,
+						Some("/type"),
+						format!("Unknown record type: {}", record_type),
+					);
 				}
 			}
 		} else {
-			return Err(anyhow!("A record has no type!"));
+			diagnostics.error(record, Some("/type"), "A record has no type!");
 		}
-
-		validate_single_record(record).unwrap();
 	}
 
-	let mut all_ids = std::collections::HashSet::new();
+	let mut all_ids = std::collections::HashMap::new();
 
 	// Check for id correctness and duplicates
 	for record in records {
-		let record_type = read_string_from_record(record, "type").unwrap();
+		let record_type = read_string_from_record(record, "type").unwrap_or_default();
 		if let anyhow::Result::Ok(id) = read_string_from_record(record, "id") {
-			println!("ID: |{}| - {}", id, record_type);
+			eprintln!("ID: |{}| - {}", id, record_type);
 
 			if id.is_empty() {
-				return Err(anyhow!("A record has an id of length 0!"));
+				diagnostics.error(record, Some("/id"), "A record has an id of length 0!");
+				continue;
 			}
 			let first = id.chars().next().unwrap();
 			if !first.is_alphabetic() && record_type != "DialogueInfo" {
-				return Err(anyhow!(
-					"Id {} doesn't start with an alphabetic character!",
-					id
-				));
+				diagnostics.error(
+					record,
+					Some("/id"),
+					format!("Id {} doesn't start with an alphabetic character!", id),
+				);
 			}
 
-			if !all_ids.insert(id.clone()) {
-				return Err(anyhow!("Duplicate id: {}", id));
+			// The engine doesn't tell apart ids that only differ in case
+			let file = read_string_from_record(record, ORIGINAL_FILE_PATH_JSON_ATTR).ok();
+			if let Some((other_id, other_file)) =
+				all_ids.insert(records::normalize_id(&id), (id.clone(), file))
+			{
+				let other_file = other_file.unwrap_or_else(|| "<unknown file>".to_string());
+				let message = if other_id == id {
+					format!("Duplicate id: {} (also defined in {})", id, other_file)
+				} else {
+					format!(
+						"Duplicate id: {} only differs in case from {} (defined in {})",
+						id, other_id, other_file
+					)
+				};
+				diagnostics.error(record, Some("/id"), message);
 			}
 		}
 	}
+}
 
-	Ok(())
+// Returns the index of the master owning a cell reference, 0 means the reference belongs to the project itself
+fn get_master_index(reference: &Value) -> u64 {
+	reference
+		.get("mast_index")
+		.and_then(|value| value.as_u64())
+		.unwrap_or(0)
 }
 
 fn read_string_from_record(record: &Value, attribute: &str) -> anyhow::Result<String> {
@@ -771,38 +1312,69 @@ fn read_string_from_record(record: &Value, attribute: &str) -> anyhow::Result<St
 }
 
 // The first argument of the closure is in heightmap adjusted tuds
-fn do_for_all_landscapes<F>(records: &mut Vec<Value>, mut closure: F) -> anyhow::Result<()>
+// Landscapes that can't be processed are reported and skipped
+fn do_for_all_landscapes<F>(records: &mut Vec<Value>, diagnostics: &mut Diagnostics, mut closure: F)
 where
 	F: FnMut(&mut f32, [i32; 2], &mut [u8]) -> anyhow::Result<()>,
 {
 	for record in records {
 		if read_string_from_record(record, "type").unwrap_or_default() == "Landscape" {
 			//
-			let grid_location = {
-				let grid_location = record.get("grid").unwrap().as_array().unwrap();
-				[
-					grid_location[0].as_i64().unwrap() as i32,
-					grid_location[1].as_i64().unwrap() as i32,
-				]
+			let grid_location = match record.get("grid").and_then(|grid| grid.as_array()) {
+				Some(grid_location)
+					if grid_location.len() == 2
+						&& grid_location.iter().all(|coordinate| coordinate.is_i64()) =>
+				{
+					[
+						grid_location[0].as_i64().unwrap() as i32,
+						grid_location[1].as_i64().unwrap() as i32,
+					]
+				}
+				_ => {
+					diagnostics.error(
+						record,
+						Some("/grid"),
+						"A landscape has no valid grid location",
+					);
+					continue;
+				}
 			};
 
 			let heights_base64 = record
 				.get("vertex_heights")
-				.unwrap()
-				.get("data")
-				.unwrap()
-				.as_str()
-				.unwrap();
-			let mut heights_data = base64::prelude::BASE64_STANDARD
-				.decode(heights_base64)
-				.unwrap();
+				.and_then(|heights| heights.get("data"))
+				.and_then(|data| data.as_str());
+			let Some(heights_base64) = heights_base64 else {
+				diagnostics.error(
+					record,
+					Some("/vertex_heights/data"),
+					"A landscape has no vertex heights",
+				);
+				continue;
+			};
+			let mut heights_data = match base64::prelude::BASE64_STANDARD.decode(heights_base64) {
+				std::result::Result::Ok(heights_data) => heights_data,
+				Err(err) => {
+					diagnostics.error(
+						record,
+						Some("/vertex_heights/data"),
+						format!("Vertex heights are not valid base64: {}", err),
+					);
+					continue;
+				}
+			};
 			const LANDSCAPE_HEIGHT_DATA_SIZE: usize = 4225;
 			if heights_data.len() != LANDSCAPE_HEIGHT_DATA_SIZE {
-				return Err(anyhow!(
-					"Wrong landscape byte length, should be {} is {}",
-					LANDSCAPE_HEIGHT_DATA_SIZE,
-					heights_data.len()
-				));
+				diagnostics.error(
+					record,
+					Some("/vertex_heights/data"),
+					format!(
+						"Wrong landscape byte length, should be {} is {}",
+						LANDSCAPE_HEIGHT_DATA_SIZE,
+						heights_data.len()
+					),
+				);
+				continue;
 			}
 			let mut height_offset = {
 				if let Some(val) = record.get("vertex_heights").unwrap().get("offset") {
@@ -817,7 +1389,10 @@ where
 				}
 			};
 
-			closure(&mut height_offset, grid_location, &mut heights_data).unwrap();
+			if let Err(err) = closure(&mut height_offset, grid_location, &mut heights_data) {
+				diagnostics.error(record, Some("/vertex_heights"), err.to_string());
+				continue;
+			}
 
 			record
 				.get_mut("vertex_heights")
@@ -835,16 +1410,15 @@ where
 				.into();
 		}
 	}
-
-	Ok(())
 }
 
-fn validate_records_together(records: &mut Vec<Value>) -> anyhow::Result<()> {
+fn validate_records_together(records: &mut Vec<Value>, diagnostics: &mut Diagnostics) {
 	let mut openmw_world = OpenmwWorld::new();
 
 	// Write data from landscapes to the openmw world
 	do_for_all_landscapes(
 		records,
+		diagnostics,
 		|height_offset: &mut f32, grid_location: [i32; 2], heights_data: &mut [u8]| {
 			let mut offset = *height_offset;
 			for iy in 0..65 {
@@ -869,12 +1443,12 @@ fn validate_records_together(records: &mut Vec<Value>) -> anyhow::Result<()> {
 
 			Ok(())
 		},
-	)
-	.unwrap();
+	);
 
 	// Read data from openmw world to landscapes
 	do_for_all_landscapes(
 		records,
+		diagnostics,
 		|height_offset: &mut f32, grid_location: [i32; 2], heights_data: &mut [u8]| {
 			let mut last_pixel = openmw_world.get_elevation_canonical([
 				grid_location[0] * SQUARES_PER_CELL as i32,
@@ -909,116 +1483,221 @@ fn validate_records_together(records: &mut Vec<Value>) -> anyhow::Result<()> {
 
 			Ok(())
 		},
-	)
-	.unwrap();
+	);
 	// panic!();
 
 	// After all data is set, we can read it back into the buffers
-	Ok(())
 }
 
-// Validates a single record upon reading
-fn validate_single_record(record: &Value) -> anyhow::Result<()> {
-	let record_type = read_string_from_record(record, "type").unwrap();
-	match record_type.as_str() {
-		"Header" => {
-			let description = read_string_from_record(record, "description").unwrap();
-			if description.len() > 256 {
-				return Err(anyhow!("Description too long! Must be under 256 bytes!"));
-			}
-
-			let author = read_string_from_record(record, "author").unwrap();
-			if author.len() > 32 {
-				return Err(anyhow!("Author name too long! Must be under 32 bytes!"));
-			}
-		}
-		_ => {}
+// Checks that only depend on the content of the record file, their results are cached
+fn validate_record_content(record: &Value, diagnostics: &mut Diagnostics) {
+	let mut conflicts = vec![];
+	merge::find_conflicts(record, String::new(), &mut conflicts);
+	for pointer in conflicts {
+		diagnostics.error(
+			record,
+			Some(&pointer),
+			"Unresolved merge conflict, replace it with the `ours` or `theirs` value.",
+		);
 	}
 
+	let record_type = read_string_from_record(record, "type").unwrap_or_default();
 	let mesh_path = read_string_from_record(record, "mesh");
 	if let anyhow::Result::Ok(mesh_path) = mesh_path {
-		if mesh_path.is_empty()
-			&& read_string_from_record(record, "type").unwrap_or_default() != "Npc"
 		// npcs are allowed to have no mesh = they use racial meshes
-		{
-			let record_path = read_string_from_record(record, ORIGINAL_FILE_PATH_JSON_ATTR);
-			return Err(anyhow!("Mesh not defined. Path: {:?}", record_path));
-		}
-		let mut path = std::env::current_dir().unwrap();
-		path.push("assets/meshes");
-		path.push(mesh_path.clone());
-		path = get_target_path(&path);
-		if !path.exists() {
-			return anyhow::Result::Err(anyhow!(
-				"Mesh path does not exist: {} ({})",
-				mesh_path,
-				path.to_string_lossy()
-			));
+		if mesh_path.is_empty() && record_type != "Npc" {
+			diagnostics.error(record, Some("/mesh"), "Mesh not defined.");
 		}
 	}
 	let icon_path = read_string_from_record(record, "icon");
 	if let anyhow::Result::Ok(icon_path) = icon_path {
 		if icon_path.is_empty() {
-			let record_path = read_string_from_record(record, ORIGINAL_FILE_PATH_JSON_ATTR);
-			return Err(anyhow!("Icon not defined. Path: {:?}", record_path));
+			diagnostics.error(record, Some("/icon"), "Icon not defined.");
 		}
-		let mut path = std::env::current_dir().unwrap();
-		path.push("assets/icons");
-		path.push(icon_path.clone());
-		path = get_target_path(&path);
-		if !path.exists() {
-			return anyhow::Result::Err(anyhow!(
-				"Icon path does not exist: {} ({})",
-				icon_path,
-				path.to_string_lossy()
-			));
+	}
+}
+
+// Checks that depend on the project or the assets, they run on every build
+fn validate_single_record(record: &Value, diagnostics: &mut Diagnostics) {
+	let record_type = read_string_from_record(record, "type").unwrap_or_default();
+	if record_type == "Header" {
+		let description = read_string_from_record(record, "description").unwrap_or_default();
+		if description.len() > 256 {
+			diagnostics.error(
+				record,
+				None,
+				"`project.description` in spicy.toml too long! Must be under 256 bytes!",
+			);
+		}
+
+		let author = read_string_from_record(record, "author").unwrap_or_default();
+		if author.len() > 32 {
+			diagnostics.error(
+				record,
+				None,
+				"`project.author` in spicy.toml too long! Must be under 32 bytes!",
+			);
 		}
 	}
-	Ok(())
+
+	let mesh_path = read_string_from_record(record, "mesh").unwrap_or_default();
+	if !mesh_path.is_empty() && !dae::asset_exists("assets/meshes", &mesh_path) {
+		diagnostics.error(
+			record,
+			Some("/mesh"),
+			format!("Mesh path does not exist: {} (in assets/meshes)", mesh_path),
+		);
+	}
+	let icon_path = read_string_from_record(record, "icon").unwrap_or_default();
+	if !icon_path.is_empty() && !dae::asset_exists("assets/icons", &icon_path) {
+		diagnostics.error(
+			record,
+			Some("/icon"),
+			format!("Icon path does not exist: {} (in assets/icons)", icon_path),
+		);
+	}
+}
+
+// The number of records the plugin gets besides the Header, dialogue infos are records of their own
+fn count_plugin_records(records: &[Value]) -> usize {
+	let info_count: usize = records
+		.iter()
+		.filter(|record| read_string_from_record(record, "type").unwrap_or_default() == "Dialogue")
+		.filter_map(|record| record.get("dialogue_infos").and_then(|infos| infos.as_array()))
+		.map(|infos| infos.len())
+		.sum();
+	records.len() + info_count
+}
+
+// The Header record, described entirely by spicy.toml
+// `record_count` counts every other record in the plugin, dialogue infos included
+fn build_header(project: &Project, record_count: usize, diagnostics: &mut Diagnostics) -> Value {
+	let mut manifest_path = env::current_dir().unwrap();
+	manifest_path.push(PROJECT_MANIFEST_FILE);
+	let mut header = serde_json::json!({
+		"type": "Header",
+		"flags": "",
+		"version": HEADER_VERSION,
+		"file_type": project.project.file_type.header_file_type(),
+		"author": project.project.author,
+		"description": project.project.description,
+		"masters": [],
+		"num_objects": record_count,
+	});
+	// Problems with the header are reported on spicy.toml, which has no JSON pointers
+	header[ORIGINAL_FILE_PATH_JSON_ATTR] = manifest_path.to_string_lossy().into();
+	match project.header_masters() {
+		std::result::Result::Ok(masters) => {
+			let masters: Vec<Value> = masters
+				.into_iter()
+				.map(|(name, size)| serde_json::json!([name, size]))
+				.collect();
+			header["masters"] = masters.into();
+		}
+		Err(err) => diagnostics.error(
+			&header,
+			None,
+			format!("`project.masters` in spicy.toml: {}", err),
+		),
+	}
+	validate_single_record(&header, diagnostics);
+	header
 }
 
 // Post-process a record by filling in values that we don't export to our custom json format
 fn fill_in_single_record(
+	project: &Project,
 	record: &mut Value,
-	record_count: usize,
 	last_reference_index: &mut usize,
-) -> anyhow::Result<()> {
-	let record_type = read_string_from_record(record, "type").unwrap();
+	diagnostics: &mut Diagnostics,
+) {
+	let record_type = read_string_from_record(record, "type").unwrap_or_default();
 
 	match record_type.as_str() {
-		"Header" => {
-			if let Err(_) = read_string_from_record(record, "num_objects") {
-				let obj = record.as_object_mut().unwrap(); //("num_objects");
-				obj.insert("num_objects".into(), record_count.into());
-			}
-		}
 		"Cell" => {
-			let obj = record
+			let master_count = project.project.masters.len() as u64;
+			let mut errors = vec![];
+			let mut warnings = vec![];
+			match record
 				.get_mut("references")
-				.unwrap()
-				.as_array_mut()
-				.unwrap();
-			for o in obj {
-				o.as_object_mut()
-					.unwrap()
-					.insert("mast_index".to_string(), 0.into());
-				o.as_object_mut()
-					.unwrap()
-					.insert("refr_index".to_string(), (*last_reference_index).into());
-				*last_reference_index += 1;
+				.and_then(|references| references.as_array_mut())
+			{
+				Some(obj) => {
+					for (idx, o) in obj.iter_mut().enumerate() {
+						let master_index = get_master_index(o);
+						if project.is_addon() && master_index != 0 {
+							// References to objects placed by a master keep their indices
+							if master_index > master_count {
+								errors.push((
+									format!("/references/{}/mast_index", idx),
+									format!(
+										"A reference to {:?} has mast_index {} but the project only has {} masters",
+										o.get("id"),
+										master_index,
+										master_count
+									),
+								));
+							}
+							if o.get("refr_index").is_none() {
+								errors.push((
+									format!("/references/{}", idx),
+									format!(
+										"A reference to {:?} has mast_index {} but no refr_index",
+										o.get("id"),
+										master_index
+									),
+								));
+							}
+							continue;
+						}
+						if master_index != 0 {
+							warnings.push((
+								format!("/references/{}/mast_index", idx),
+								format!(
+									"A reference to {:?} has mast_index {}, which is ignored outside of omwaddon projects",
+									o.get("id"),
+									master_index
+								),
+							));
+						}
+						o.as_object_mut()
+							.unwrap()
+							.insert("mast_index".to_string(), 0.into());
+						// References keep their index, only new ones get the next free one
+						if reference_indices::get_reference_index(o).is_none() {
+							o.as_object_mut()
+								.unwrap()
+								.insert("refr_index".to_string(), (*last_reference_index).into());
+							*last_reference_index += 1;
+						}
+					}
+				}
+				None => errors.push((
+					"/references".to_string(),
+					"A cell has no references array".to_string(),
+				)),
+			}
+			for (field, message) in errors {
+				diagnostics.error(record, Some(&field), message);
+			}
+			for (field, message) in warnings {
+				diagnostics.warning(record, Some(&field), message);
 			}
 		}
 		"Landscape" => {
 			let landscape_flags = record
 				.get("landscape_flags")
-				.expect("Landscape flags are compulsory but missing on a landscape record!")
-				.as_str()
-				.unwrap();
-			if landscape_flags.is_empty() {
-				return Err(anyhow!("A landscape has an empty landscape flags string. This indicates an error. Perhaps the tool used to edit it has an internal bug.unwrap() A good default value is 'USES_VERTEX_HEIGHTS_AND_NORMALS | USES_TEXTURES'"));
+				.and_then(|flags| flags.as_str());
+			match landscape_flags {
+				None => diagnostics.error(
+					record,
+					Some("/landscape_flags"),
+					"Landscape flags are compulsory but missing on a landscape record!",
+				),
+				Some("") => diagnostics.error(record, Some("/landscape_flags"), "A landscape has an empty landscape flags string. This indicates an error. Perhaps the tool used to edit it has an internal bug. A good default value is 'USES_VERTEX_HEIGHTS_AND_NORMALS | USES_TEXTURES'"),
+				Some(_) => {}
 			}
 		}
 		_ => {}
 	}
-	Ok(())
 }
@@ -0,0 +1,305 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	fs,
+	process::Command,
+};
+
+use anyhow::anyhow;
+use serde_json::{Map, Value};
+
+use crate::{format, records};
+
+// Conflicting values are replaced with an object holding this key,
+// so that the validator can point at every conflict left in a record
+pub const MERGE_CONFLICT_KEY: &str = "spicy_merge_conflict";
+
+// Three-way merge of a record file, called by git with the %O %A %B placeholders
+// The result is written over `ours`, git treats an error as an unresolved conflict
+pub fn merge_driver(base_path: &str, ours_path: &str, theirs_path: &str) -> anyhow::Result<()> {
+	let base_content = fs::read_to_string(base_path)?;
+	let ours = fs::read_to_string(ours_path)?;
+	let theirs = fs::read_to_string(theirs_path)?;
+
+	// Files added on both sides come with an empty base
+	let base = if base_content.trim().is_empty() {
+		Some(None)
+	} else {
+		serde_json::from_str::<Value>(&base_content).ok().map(Some)
+	};
+	let (Some(base), Ok(ours), Ok(theirs)) = (
+		base,
+		serde_json::from_str::<Value>(&ours),
+		serde_json::from_str::<Value>(&theirs),
+	) else {
+		// Not a record, fall back to a line based merge with the usual conflict markers
+		return merge_text(base_path, ours_path, theirs_path);
+	};
+
+	let merged = merge_values(base.as_ref(), Some(&ours), Some(&theirs), "").unwrap_or(Value::Null);
+	fs::write(ours_path, format::format_record(&merged))?;
+
+	let mut conflicts = vec![];
+	find_conflicts(&merged, String::new(), &mut conflicts);
+	if !conflicts.is_empty() {
+		// git passes temporary files, it reports the path of the record itself
+		for pointer in &conflicts {
+			eprintln!("Merge conflict at {}", pointer);
+		}
+		return Err(anyhow!("{} merge conflicts", conflicts.len()));
+	}
+
+	Ok(())
+}
+
+// Collects JSON pointers to every conflict marker left in a record
+pub fn find_conflicts(value: &Value, pointer: String, conflicts: &mut Vec<String>) {
+	match value {
+		Value::Object(object) => {
+			if object.contains_key(MERGE_CONFLICT_KEY) {
+				conflicts.push(pointer);
+				return;
+			}
+			for (key, value) in object {
+				find_conflicts(
+					value,
+					format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1")),
+					conflicts,
+				);
+			}
+		}
+		Value::Array(array) => {
+			for (index, value) in array.iter().enumerate() {
+				find_conflicts(value, format!("{}/{}", pointer, index), conflicts);
+			}
+		}
+		_ => {}
+	}
+}
+
+fn merge_text(base_path: &str, ours_path: &str, theirs_path: &str) -> anyhow::Result<()> {
+	let status = Command::new("git")
+		.args(["merge-file", "-L", "ours", "-L", "base", "-L", "theirs"])
+		.args([ours_path, base_path, theirs_path])
+		.status()?;
+	if !status.success() {
+		return Err(anyhow!("merge conflicts in a file that isn't valid json"));
+	}
+
+	Ok(())
+}
+
+// Returns None when the value was removed
+// The field is the key the value is stored under, empty for array elements and the record itself
+fn merge_values(
+	base: Option<&Value>,
+	ours: Option<&Value>,
+	theirs: Option<&Value>,
+	field: &str,
+) -> Option<Value> {
+	if ours == theirs || base == theirs {
+		return ours.cloned();
+	}
+	if base == ours {
+		return theirs.cloned();
+	}
+
+	match (ours, theirs) {
+		(Some(Value::Object(ours_object)), Some(Value::Object(theirs_object))) => {
+			let base_object = base.and_then(|base| base.as_object());
+			let mut merged = Map::new();
+			for key in ours_object.keys().chain(theirs_object.keys()) {
+				if merged.contains_key(key) {
+					continue;
+				}
+				let value = merge_values(
+					base_object.and_then(|base| base.get(key)),
+					ours_object.get(key),
+					theirs_object.get(key),
+					key,
+				);
+				if let Some(value) = value {
+					merged.insert(key.clone(), value);
+				}
+			}
+			Some(Value::Object(merged))
+		}
+		(Some(Value::Array(ours_array)), Some(Value::Array(theirs_array))) => {
+			let base_array = base
+				.and_then(|base| base.as_array())
+				.map(|base| base.as_slice())
+				.unwrap_or_default();
+			if is_list(field) {
+				Some(Value::Array(merge_arrays(
+					base_array,
+					ours_array,
+					theirs_array,
+				)))
+			} else if base.is_some()
+				&& base_array.len() == ours_array.len()
+				&& base_array.len() == theirs_array.len()
+			{
+				// Anything else is positional, like coordinates, skills or rank names
+				let merged = base_array
+					.iter()
+					.zip(ours_array)
+					.zip(theirs_array)
+					// Both sides have every element, so none of them is removed
+					.map(|((base, ours), theirs)| {
+						merge_values(Some(base), Some(ours), Some(theirs), "").unwrap()
+					})
+					.collect();
+				Some(Value::Array(merged))
+			} else {
+				Some(conflict(base, ours, theirs))
+			}
+		}
+		_ => Some(conflict(base, ours, theirs)),
+	}
+}
+
+// Arrays whose elements are identified by what they hold rather than their position, elements can
+// be added, removed and edited on both sides
+fn is_list(field: &str) -> bool {
+	matches!(
+		field,
+		"references" | "dialogue_infos" | "inventory" | "spells" | "items" | "creatures"
+	)
+}
+
+// Keeps the order of `ours`, elements added by `theirs` are placed after the element they followed
+fn merge_arrays(base: &[Value], ours: &[Value], theirs: &[Value]) -> Vec<Value> {
+	let ours_matches = match_elements(base, ours);
+	let theirs_matches = match_elements(base, theirs);
+	let mut base_to_theirs = vec![None; base.len()];
+	for (index, base_index) in theirs_matches.iter().enumerate() {
+		if let Some(base_index) = base_index {
+			base_to_theirs[*base_index] = Some(index);
+		}
+	}
+
+	// Merged elements along with the base element they came from
+	let mut merged: Vec<(Value, Option<usize>)> = vec![];
+	for (index, element) in ours.iter().enumerate() {
+		match ours_matches[index] {
+			Some(base_index) => {
+				let theirs_element = base_to_theirs[base_index].map(|index| &theirs[index]);
+				if let Some(value) =
+					merge_values(Some(&base[base_index]), Some(element), theirs_element, "")
+				{
+					merged.push((value, Some(base_index)));
+				}
+			}
+			None => merged.push((element.clone(), None)),
+		}
+	}
+
+	let mut cursor = 0;
+	for (index, element) in theirs.iter().enumerate() {
+		match theirs_matches[index] {
+			Some(base_index) => {
+				if let Some(position) = merged
+					.iter()
+					.position(|(_, origin)| *origin == Some(base_index))
+				{
+					cursor = position + 1;
+				} else if *element != base[base_index] {
+					// Removed by ours but edited by theirs
+					let value = conflict(Some(&base[base_index]), None, Some(element));
+					merged.insert(cursor, (value, Some(base_index)));
+					cursor += 1;
+				}
+			}
+			None => {
+				// Both sides added the same element
+				let added_by_ours = merged
+					.iter()
+					.any(|(value, origin)| origin.is_none() && value == element);
+				if !added_by_ours {
+					merged.insert(cursor, (element.clone(), None));
+					cursor += 1;
+				}
+			}
+		}
+	}
+
+	merged.into_iter().map(|(value, _)| value).collect()
+}
+
+// Finds the base element each element of a side came from
+// Unchanged elements are matched first, then edited ones by their identity
+fn match_elements(base: &[Value], side: &[Value]) -> Vec<Option<usize>> {
+	let mut matches = vec![None; side.len()];
+	let mut used = vec![false; base.len()];
+
+	let mut unchanged: HashMap<String, VecDeque<usize>> = HashMap::new();
+	for (index, element) in base.iter().enumerate() {
+		unchanged
+			.entry(element.to_string())
+			.or_default()
+			.push_back(index);
+	}
+	for (index, element) in side.iter().enumerate() {
+		if let Some(base_index) = unchanged
+			.get_mut(&element.to_string())
+			.and_then(|indices| indices.pop_front())
+		{
+			matches[index] = Some(base_index);
+			used[base_index] = true;
+		}
+	}
+
+	let mut identities: HashMap<String, VecDeque<usize>> = HashMap::new();
+	for (index, element) in base.iter().enumerate() {
+		if let (false, Some(identity)) = (used[index], get_identity(element)) {
+			identities.entry(identity).or_default().push_back(index);
+		}
+	}
+	for (index, element) in side.iter().enumerate() {
+		if matches[index].is_some() {
+			continue;
+		}
+		matches[index] = get_identity(element)
+			.and_then(|identity| identities.get_mut(&identity))
+			.and_then(|indices| indices.pop_front());
+	}
+
+	matches
+}
+
+// What makes an element the same element after it was edited
+fn get_identity(element: &Value) -> Option<String> {
+	match element {
+		Value::Object(object) => {
+			// References placed by masters
+			// The project's own references have no mast_index in their files
+			if let Some(refr_index) = object.get("refr_index") {
+				let mast_index = object.get("mast_index").and_then(|index| index.as_u64()).unwrap_or(0);
+				return Some(format!("reference:{}:{}", mast_index, refr_index));
+			}
+			if let Some(id) = object.get("id").and_then(|id| id.as_str()) {
+				return Some(format!("id:{}", records::normalize_id(id)));
+			}
+			// Dialogue infos
+			if let Some(text) = object.get("text").and_then(|text| text.as_str()) {
+				return Some(format!("text:{}", text));
+			}
+			None
+		}
+		// Inventories and leveled lists
+		Value::Array(array) => array
+			.iter()
+			.find_map(|value| value.as_str())
+			.map(|id| format!("id:{}", records::normalize_id(id))),
+		_ => None,
+	}
+}
+
+fn conflict(base: Option<&Value>, ours: Option<&Value>, theirs: Option<&Value>) -> Value {
+	serde_json::json!({
+		MERGE_CONFLICT_KEY: {
+			"base": base,
+			"ours": ours,
+			"theirs": theirs,
+		}
+	})
+}
@@ -0,0 +1,174 @@
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{constants::PROJECT_MANIFEST_FILE, launch::LaunchProfile};
+
+// The contents of `spicy.toml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Project {
+	pub project: ProjectSettings,
+	pub tools: ToolPaths,
+	pub stats: StatsSettings,
+	// Named sets of `spicy run` options
+	pub profiles: BTreeMap<String, LaunchProfile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectSettings {
+	pub name: String,
+	pub author: String,
+	pub description: String,
+	// Name of the compiled plugin, without the extension
+	pub output: String,
+	pub file_type: FileType,
+	// Paths to the plugins this project depends on
+	pub masters: Vec<String>,
+}
+
+impl Default for ProjectSettings {
+	fn default() -> Self {
+		ProjectSettings {
+			name: String::new(),
+			author: "Author".to_string(),
+			description: "Spicy plugin for OpenMW".to_string(),
+			output: "out".to_string(),
+			file_type: FileType::default(),
+			masters: vec![],
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileType {
+	#[default]
+	Omwgame,
+	Omwaddon,
+}
+
+impl FileType {
+	pub fn extension(&self) -> &'static str {
+		match self {
+			FileType::Omwgame => "omwgame",
+			FileType::Omwaddon => "omwaddon",
+		}
+	}
+
+	// The value tes3conv expects in the `file_type` field of the Header record
+	pub fn header_file_type(&self) -> &'static str {
+		match self {
+			FileType::Omwgame => "Esm",
+			FileType::Omwaddon => "Esp",
+		}
+	}
+}
+
+// Overrides for the bundled tools. Relative paths are resolved against the project directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolPaths {
+	pub tes3conv: Option<PathBuf>,
+	pub openmw: Option<PathBuf>,
+	pub openmw_cs: Option<PathBuf>,
+}
+
+// Settings of `spicy stats`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatsSettings {
+	// Assets that are used without being referenced by a record, relative to the assets directory
+	// Every asset whose path starts with one of these is never reported as unused
+	pub ignored_assets: Vec<String>,
+}
+
+impl Project {
+	// Creates the manifest for a freshly created project
+	pub fn new(name: &str) -> Self {
+		let mut project = Project::default();
+		project.project.name = name.to_string();
+		project
+	}
+
+	// Reads `spicy.toml` from the current directory
+	pub fn load() -> anyhow::Result<Self> {
+		let mut path = env::current_dir().unwrap();
+		path.push(PROJECT_MANIFEST_FILE);
+		if !path.exists() {
+			return Err(anyhow!(
+				"could not find `{}` in `{}`",
+				PROJECT_MANIFEST_FILE,
+				path.to_string_lossy()
+			));
+		}
+
+		let content = fs::read_to_string(&path)?;
+		let project: Project = toml::from_str(&content)
+			.with_context(|| format!("failed to parse `{}`", path.to_string_lossy()))?;
+
+		Ok(project)
+	}
+
+	pub fn to_toml(&self) -> anyhow::Result<String> {
+		Ok(format!(
+			"# The project was created by spicy\n\n{}",
+			toml::to_string_pretty(self)?
+		))
+	}
+
+	// File name of the compiled plugin, for example `out.omwgame`
+	pub fn output_file_name(&self) -> String {
+		format!(
+			"{}.{}",
+			self.project.output,
+			self.project.file_type.extension()
+		)
+	}
+
+	// Addons are built against masters and keep references into them
+	pub fn is_addon(&self) -> bool {
+		self.project.file_type == FileType::Omwaddon
+	}
+
+	// Resolves the master paths against the project directory
+	pub fn master_paths(&self) -> Vec<PathBuf> {
+		self.project
+			.masters
+			.iter()
+			.map(|master| {
+				let mut path = env::current_dir().unwrap();
+				path.push(master);
+				path
+			})
+			.collect()
+	}
+
+	// The masters as listed in the Header record - file names with file sizes
+	pub fn header_masters(&self) -> anyhow::Result<Vec<(String, u64)>> {
+		let mut masters = vec![];
+		for path in self.master_paths() {
+			let metadata = fs::metadata(&path).with_context(|| {
+				format!("master file `{}` does not exist", path.to_string_lossy())
+			})?;
+			let name = path
+				.file_name()
+				.ok_or_else(|| anyhow!("invalid master path `{}`", path.to_string_lossy()))?
+				.to_string_lossy()
+				.to_string();
+			masters.push((name, metadata.len()));
+		}
+
+		Ok(masters)
+	}
+
+	// Path of the compiled plugin in the build directory
+	pub fn output_path(&self) -> PathBuf {
+		let mut path = env::current_dir().unwrap();
+		path.push("build");
+		path.push(self.output_file_name());
+		path
+	}
+}
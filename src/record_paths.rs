@@ -0,0 +1,107 @@
+use std::{
+	collections::BTreeMap,
+	env, fs,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+	constants::ORIGINAL_FILE_PATH_JSON_ATTR, process_directory, read_string_from_record, records,
+};
+
+const RECORD_PATHS_FILE: &str = "cache/record_paths.json";
+
+// Where each record was read from on the last compile, so decompile can write it back there
+// Paths are relative to common/data and use forward slashes
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RecordPaths {
+	paths: BTreeMap<String, String>,
+}
+
+// Identifies a record across a compile and decompile
+// Ids are case insensitive in the engine and openmw-cs doesn't keep their case in every case
+fn get_record_path_key(record: &Value) -> String {
+	let record_type = read_string_from_record(record, "type").unwrap_or_default();
+	match records::get_record_key(record) {
+		Some(key) => format!("{}:{}", record_type, records::normalize_id(&key)),
+		None => record_type,
+	}
+}
+
+impl RecordPaths {
+	// Collects the paths of compiled records from the path spicy stores on every record it reads
+	pub fn from_records(records: &[Value]) -> Self {
+		let data_path = get_data_path();
+		let mut record_paths = RecordPaths::default();
+		for record in records {
+			let Some(path) = record
+				.get(ORIGINAL_FILE_PATH_JSON_ATTR)
+				.and_then(|path| path.as_str())
+			else {
+				continue;
+			};
+			let Ok(relative_path) = Path::new(path).strip_prefix(&data_path) else {
+				continue;
+			};
+			record_paths
+				.paths
+				.entry(get_record_path_key(record))
+				.or_insert_with(|| relative_path.to_string_lossy().replace('\\', "/"));
+		}
+		record_paths
+	}
+
+	// Loads the paths stored by the last compile
+	// Without them, like after `spicy clear`, the record files in common/data are read instead
+	pub fn load() -> Self {
+		let mut path = env::current_dir().unwrap();
+		path.push(RECORD_PATHS_FILE);
+		if let Some(record_paths) = fs::read_to_string(path)
+			.ok()
+			.and_then(|content| serde_json::from_str(&content).ok())
+		{
+			return record_paths;
+		}
+
+		let mut files = vec![];
+		if process_directory(get_data_path(), &mut files).is_err() {
+			return RecordPaths::default();
+		}
+		files.sort();
+		let records: Vec<Value> = files
+			.iter()
+			.filter_map(|file| {
+				let mut record: Value = serde_json::from_str(&fs::read_to_string(file).ok()?).ok()?;
+				record.as_object_mut()?.insert(
+					ORIGINAL_FILE_PATH_JSON_ATTR.to_string(),
+					file.to_string_lossy().to_string().into(),
+				);
+				Some(record)
+			})
+			.collect();
+		RecordPaths::from_records(&records)
+	}
+
+	pub fn save(&self) -> anyhow::Result<()> {
+		let mut path = env::current_dir().unwrap();
+		path.push(RECORD_PATHS_FILE);
+		fs::create_dir_all(path.parent().unwrap())?;
+		fs::write(path, serde_json::to_string(self)?)?;
+		Ok(())
+	}
+
+	// The file the record was compiled from, relative to common/data
+	pub fn get(&self, record: &Value) -> Option<PathBuf> {
+		self.paths
+			.get(&get_record_path_key(record))
+			.map(PathBuf::from)
+	}
+}
+
+fn get_data_path() -> PathBuf {
+	let mut data_path = env::current_dir().unwrap();
+	data_path.push("common/data");
+	data_path
+}
@@ -1,3 +1,10 @@
+use anyhow::anyhow;
+use base64::Engine;
+use serde_json::{json, Value};
+
+// A landscape has 65x65 vertices
+const LANDSCAPE_VERTEX_COUNT: usize = 65 * 65;
+
 pub fn get_record_types() -> Vec<String> {
 	vec![
 		"Header".to_string(),
@@ -45,3 +52,742 @@ pub fn get_record_types() -> Vec<String> {
 		//"DialogueInfo".to_string(), // Handled differently... To simplify usage, we embed them on dialogue records.
 	]
 }
+
+// Skills and magic effects are fixed by the engine, tes3conv only accepts these ids
+const SKILL_IDS: [&str; 27] = [
+	"Block", "Armorer", "MediumArmor", "HeavyArmor", "BluntWeapon", "LongBlade", "Axe", "Spear",
+	"Athletics", "Enchant", "Destruction", "Alteration", "Illusion", "Conjuration", "Mysticism",
+	"Restoration", "Alchemy", "Unarmored", "Security", "Sneak", "Acrobatics", "LightArmor",
+	"ShortBlade", "Marksman", "Mercantile", "Speechcraft", "HandToHand",
+];
+
+const MAGIC_EFFECT_IDS: [&str; 143] = [
+	"WaterBreathing", "SwiftSwim", "WaterWalking", "Shield", "FireShield", "LightningShield",
+	"FrostShield", "Burden", "Feather", "Jump", "Levitate", "SlowFall", "Lock", "Open",
+	"FireDamage", "ShockDamage", "FrostDamage", "DrainAttribute", "DrainHealth", "DrainMagicka",
+	"DrainFatigue", "DrainSkill", "DamageAttribute", "DamageHealth", "DamageMagicka",
+	"DamageFatigue", "DamageSkill", "Poison", "WeaknessToFire", "WeaknessToFrost",
+	"WeaknessToShock", "WeaknessToMagicka", "WeaknessToCommonDisease", "WeaknessToBlightDisease",
+	"WeaknessToCorprus", "WeaknessToPoison", "WeaknessToNormalWeapons", "DisintegrateWeapon",
+	"DisintegrateArmor", "Invisibility", "Chameleon", "Light", "Sanctuary", "NightEye", "Charm",
+	"Paralyze", "Silence", "Blind", "Sound", "CalmHumanoid", "CalmCreature", "FrenzyHumanoid",
+	"FrenzyCreature", "DemoralizeHumanoid", "DemoralizeCreature", "RallyHumanoid", "RallyCreature",
+	"Dispel", "SoulTrap", "Telekinesis", "Mark", "Recall", "DivineIntervention",
+	"AlmsiviIntervention", "DetectAnimal", "DetectEnchantment", "DetectKey", "SpellAbsorption",
+	"Reflect", "CureCommonDisease", "CureBlightDisease", "CureCorprus", "CurePoison",
+	"CureParalyzation", "RestoreAttribute", "RestoreHealth", "RestoreMagicka", "RestoreFatigue",
+	"RestoreSkill", "FortifyAttribute", "FortifyHealth", "FortifyMagicka", "FortifyFatigue",
+	"FortifySkill", "FortifyMagickaMultiplier", "AbsorbAttribute", "AbsorbHealth", "AbsorbMagicka",
+	"AbsorbFatigue", "AbsorbSkill", "ResistFire", "ResistFrost", "ResistShock", "ResistMagicka",
+	"ResistCommonDisease", "ResistBlightDisease", "ResistCorprus", "ResistPoison",
+	"ResistNormalWeapons", "ResistParalysis", "RemoveCurse", "TurnUndead", "SummonScamp",
+	"SummonClannfear", "SummonDaedroth", "SummonDremora", "SummonGhost", "SummonSkeleton",
+	"SummonLeastBonewalker", "SummonGreaterBonewalker", "SummonBonelord", "SummonTwilight",
+	"SummonHunger", "SummonGoldenSaint", "SummonFlameAtronach", "SummonFrostAtronach",
+	"SummonStormAtronach", "FortifyAttackBonus", "CommandCreature", "CommandHumanoid",
+	"BoundDagger", "BoundLongsword", "BoundMace", "BoundBattleAxe", "BoundSpear", "BoundLongbow",
+	"ExtraSpell", "BoundCuirass", "BoundHelm", "BoundBoots", "BoundShield", "BoundGloves",
+	"Corprus", "Vampirism", "SummonCenturionSphere", "SunDamage", "StuntedMagicka",
+	"SummonFabricant", "SummonWolf", "SummonBear", "SummonBoneWolf", "Summon04", "Summon05",
+];
+
+// Records that are identified by something other than their `id`
+// Objects with an `id` share one namespace, these are only unique within their type
+pub fn get_record_key(record: &Value) -> Option<String> {
+	let record_type = record.get("type")?.as_str()?;
+	let key = match record_type {
+		"Header" => return None,
+		"Cell" => {
+			let name = record.get("name")?.as_str()?;
+			let is_interior = record
+				.get("data")
+				.and_then(|data| data.get("flags"))
+				.and_then(|flags| flags.as_str())
+				.unwrap_or_default()
+				.contains("IS_INTERIOR");
+			if is_interior {
+				name.to_string()
+			} else {
+				format_grid(record.get("data")?.get("grid")?)?
+			}
+		}
+		"Landscape" => format_grid(record.get("grid")?)?,
+		"PathGrid" => {
+			let cell = record.get("cell")?.as_str()?;
+			if cell.is_empty() {
+				format_grid(record.get("data")?.get("grid")?)?
+			} else {
+				cell.to_string()
+			}
+		}
+		"Skill" => record.get("skill_id")?.as_str()?.to_string(),
+		"MagicEffect" => record.get("effect_id")?.as_str()?.to_string(),
+		_ => record.get("id")?.as_str()?.to_string(),
+	};
+
+	Some(key)
+}
+
+// Ids are compared the way the engine compares them, ignoring the case of ascii letters only
+pub fn normalize_id(id: &str) -> String {
+	id.to_ascii_lowercase()
+}
+
+// Types of the records that can be carried in inventories
+pub const ITEM_TYPES: &[&str] = &[
+	"Alchemy",
+	"Apparatus",
+	"Armor",
+	"Book",
+	"Clothing",
+	"Ingredient",
+	"LeveledItem",
+	"Light",
+	"Lockpick",
+	"MiscItem",
+	"Probe",
+	"RepairItem",
+	"Weapon",
+];
+
+// Types of the records that can be placed in cells
+pub const OBJECT_TYPES: &[&str] = &[
+	"Activator",
+	"Alchemy",
+	"Apparatus",
+	"Armor",
+	"Book",
+	"Clothing",
+	"Container",
+	"Creature",
+	"Door",
+	"Ingredient",
+	"LeveledCreature",
+	"LeveledItem",
+	"Light",
+	"Lockpick",
+	"MiscItem",
+	"Npc",
+	"Probe",
+	"RepairItem",
+	"Static",
+	"Weapon",
+];
+
+pub const ACTOR_TYPES: &[&str] = &["Creature", "Npc"];
+
+// Whether records of this type share the engine's namespace of objects, the other types have one of their own
+pub fn is_object_type(record_type: &str) -> bool {
+	OBJECT_TYPES.contains(&record_type) || record_type == "Bodypart"
+}
+
+// Whether records of this type are identified by their id
+pub fn uses_object_id(record_type: &str) -> bool {
+	!matches!(
+		record_type,
+		"Header" | "Cell" | "Landscape" | "PathGrid" | "Skill" | "MagicEffect"
+	)
+}
+
+fn format_grid(grid: &Value) -> Option<String> {
+	let grid = grid.as_array()?;
+	Some(format!("{},{}", grid.first()?.as_i64()?, grid.get(1)?.as_i64()?))
+}
+
+// Finds a skill or magic effect id ignoring case, the record gets the spelling tes3conv expects
+fn find_fixed_id(ids: &[&'static str], key: &str, kind: &str) -> anyhow::Result<&'static str> {
+	ids.iter()
+		.find(|id| id.eq_ignore_ascii_case(key))
+		.copied()
+		.ok_or_else(|| anyhow!("`{}` is not a {}, use one of: {}", key, kind, ids.join(", ")))
+}
+
+// Parses exterior cell coordinates written as `x,y`
+pub fn parse_grid(key: &str) -> Option<[i64; 2]> {
+	let (x, y) = key.split_once(',')?;
+	Some([x.trim().parse().ok()?, y.trim().parse().ok()?])
+}
+
+// A new record with every field tes3conv requires, filled with defaults
+// `key` is the id of the record, or whatever `get_record_key` returns for its type
+pub fn get_record_template(record_type: &str, key: &str) -> anyhow::Result<Value> {
+	let record = match record_type {
+		"Header" => {
+			return Err(anyhow!(
+				"the Header record is derived from spicy.toml on compile, edit spicy.toml instead"
+			))
+		}
+		"GameSetting" => json!({
+			"type": "GameSetting",
+			"flags": "",
+			"id": key,
+			"value": { "type": "Float", "data": 0.0 }
+		}),
+		"GlobalVariable" => json!({
+			"type": "GlobalVariable",
+			"flags": "",
+			"id": key,
+			"global_type": "Short",
+			"value": 0.0
+		}),
+		"Class" => json!({
+			"type": "Class",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"description": "",
+			"data": {
+				"attribute1": "None",
+				"attribute2": "None",
+				"specialization": "None",
+				"major1": "None",
+				"major2": "None",
+				"major3": "None",
+				"major4": "None",
+				"major5": "None",
+				"minor1": "None",
+				"minor2": "None",
+				"minor3": "None",
+				"minor4": "None",
+				"minor5": "None",
+				"flags": "",
+				"services": ""
+			}
+		}),
+		"Faction" => {
+			let requirement = json!({
+				"attributes": [0, 0],
+				"primary_skill": 0,
+				"favored_skill": 0,
+				"reputation": 0
+			});
+			json!({
+				"type": "Faction",
+				"flags": "",
+				"id": key,
+				"name": key,
+				"rank_names": [],
+				"reactions": [],
+				"data": {
+					"favored_attributes": ["None", "None"],
+					"requirements": vec![requirement; 10],
+					"favored_skills": vec!["None"; 7],
+					"flags": ""
+				}
+			})
+		}
+		"Race" => json!({
+			"type": "Race",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"spells": [],
+			"description": "",
+			"data": {
+				"skill_bonuses": {
+					"skill_0": "None", "bonus_0": 0,
+					"skill_1": "None", "bonus_1": 0,
+					"skill_2": "None", "bonus_2": 0,
+					"skill_3": "None", "bonus_3": 0,
+					"skill_4": "None", "bonus_4": 0,
+					"skill_5": "None", "bonus_5": 0,
+					"skill_6": "None", "bonus_6": 0
+				},
+				"strength": [40, 40],
+				"intelligence": [40, 40],
+				"willpower": [40, 40],
+				"agility": [40, 40],
+				"speed": [40, 40],
+				"endurance": [40, 40],
+				"personality": [40, 40],
+				"luck": [40, 40],
+				"height": [1.0, 1.0],
+				"weight": [1.0, 1.0],
+				"flags": "PLAYABLE"
+			}
+		}),
+		"Sound" => json!({
+			"type": "Sound",
+			"flags": "",
+			"id": key,
+			"sound_path": "",
+			"data": { "volume": 255, "range": [0, 255] }
+		}),
+		"SoundGen" => json!({
+			"type": "SoundGen",
+			"flags": "",
+			"id": key,
+			"sound_gen_type": "LeftFoot",
+			"creature": "",
+			"sound": ""
+		}),
+		"Skill" => json!({
+			"type": "Skill",
+			"flags": "",
+			"skill_id": find_fixed_id(&SKILL_IDS, key, "skill")?,
+			"description": "",
+			"data": {
+				"governing_attribute": 0,
+				"specialization": 0,
+				"actions": [1.0, 1.0, 1.0, 1.0]
+			}
+		}),
+		"MagicEffect" => json!({
+			"type": "MagicEffect",
+			"flags": "",
+			"effect_id": find_fixed_id(&MAGIC_EFFECT_IDS, key, "magic effect")?,
+			"icon": "",
+			"texture": "",
+			"bolt_sound": "",
+			"cast_sound": "",
+			"hit_sound": "",
+			"area_sound": "",
+			"cast_visual": "",
+			"bolt_visual": "",
+			"hit_visual": "",
+			"area_visual": "",
+			"description": "",
+			"data": {
+				"school": "Alteration",
+				"base_cost": 1.0,
+				"flags": "",
+				"color": [255, 255, 255],
+				"speed": 1.0,
+				"size": 1.0,
+				"size_cap": 1.0
+			}
+		}),
+		"Script" => json!({
+			"type": "Script",
+			"flags": "",
+			"id": key,
+			"header": {
+				"num_shorts": 0,
+				"num_longs": 0,
+				"num_floats": 0,
+				"bytecode_length": 0,
+				"variables_length": 0
+			},
+			"variables": "AAAAAA==",
+			"bytecode": "AAAAAA==",
+			"text": format!("Begin {}\n\nEnd {}\n", key, key)
+		}),
+		"Region" => json!({
+			"type": "Region",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"weather_chances": {
+				"clear": 100,
+				"cloudy": 0,
+				"foggy": 0,
+				"overcast": 0,
+				"rain": 0,
+				"thunder": 0,
+				"ash": 0,
+				"blight": 0,
+				"snow": 0,
+				"blizzard": 0
+			},
+			"sleep_creature": "",
+			"map_color": [0, 0, 0, 0],
+			"sounds": []
+		}),
+		"StartScript" => json!({
+			"type": "StartScript",
+			"flags": "",
+			"id": key,
+			"script": ""
+		}),
+		"Birthsign" => json!({
+			"type": "Birthsign",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"texture": "",
+			"description": "",
+			"spells": []
+		}),
+		"LandscapeTexture" => json!({
+			"type": "LandscapeTexture",
+			"flags": "",
+			"id": key,
+			"index": 0,
+			"file_name": ""
+		}),
+		"Spell" => json!({
+			"type": "Spell",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"effects": [],
+			"data": { "spell_type": "Spell", "cost": 0, "flags": "" }
+		}),
+		"Static" => json!({
+			"type": "Static",
+			"flags": "",
+			"id": key,
+			"mesh": ""
+		}),
+		"Door" => json!({
+			"type": "Door",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"open_sound": "",
+			"close_sound": ""
+		}),
+		"MiscItem" => json!({
+			"type": "MiscItem",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"data": { "weight": 1.0, "value": 1, "flags": "" }
+		}),
+		"Weapon" => json!({
+			"type": "Weapon",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"enchanting": "",
+			"data": {
+				"weight": 1.0,
+				"value": 1,
+				"weapon_type": "ShortBladeOneHand",
+				"health": 100,
+				"speed": 1.0,
+				"reach": 1.0,
+				"enchantment": 0,
+				"chop_min": 1,
+				"chop_max": 1,
+				"slash_min": 1,
+				"slash_max": 1,
+				"thrust_min": 1,
+				"thrust_max": 1,
+				"flags": ""
+			}
+		}),
+		"Container" => json!({
+			"type": "Container",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"encumbrance": 100.0,
+			"container_flags": "",
+			"inventory": []
+		}),
+		"Creature" => json!({
+			"type": "Creature",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"inventory": [],
+			"spells": [],
+			"ai_data": { "hello": 30, "fight": 30, "flee": 30, "alarm": 0, "services": "" },
+			"ai_packages": [],
+			"travel_destinations": [],
+			"sound": "",
+			"creature_flags": "",
+			"blood_type": 0,
+			"data": {
+				"creature_type": "Normal",
+				"level": 1,
+				"strength": 50,
+				"intelligence": 50,
+				"willpower": 50,
+				"agility": 50,
+				"speed": 50,
+				"endurance": 50,
+				"personality": 50,
+				"luck": 50,
+				"health": 50,
+				"magicka": 50,
+				"fatigue": 50,
+				"soul": 0,
+				"combat": 50,
+				"magic": 50,
+				"stealth": 50,
+				"attack1": [1, 1],
+				"attack2": [1, 1],
+				"attack3": [1, 1],
+				"gold": 0
+			}
+		}),
+		"Bodypart" => json!({
+			"type": "Bodypart",
+			"flags": "",
+			"id": key,
+			"race": "",
+			"mesh": "",
+			"data": {
+				"part": "Head",
+				"vampire": false,
+				"flags": "",
+				"bodypart_type": "Skin"
+			}
+		}),
+		"Light" => json!({
+			"type": "Light",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"sound": "",
+			"data": {
+				"weight": 1.0,
+				"value": 1,
+				"time": -1,
+				"radius": 256,
+				"color": [255, 255, 255, 0],
+				"flags": ""
+			}
+		}),
+		"Enchanting" => json!({
+			"type": "Enchanting",
+			"flags": "",
+			"id": key,
+			"effects": [],
+			"data": { "enchant_type": "CastOnce", "cost": 0, "max_charge": 0, "flags": "" }
+		}),
+		"Npc" => json!({
+			"type": "Npc",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"inventory": [],
+			"spells": [],
+			"ai_data": { "hello": 30, "fight": 30, "flee": 30, "alarm": 0, "services": "" },
+			"ai_packages": [],
+			"travel_destinations": [],
+			"race": "",
+			"class": "",
+			"faction": "",
+			"head": "",
+			"hair": "",
+			"npc_flags": "AUTO_CALCULATE",
+			"blood_type": 0,
+			"data": { "level": 1, "disposition": 50, "reputation": 0, "rank": 0, "gold": 0 }
+		}),
+		"Armor" => json!({
+			"type": "Armor",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"enchanting": "",
+			"biped_objects": [],
+			"data": {
+				"armor_type": "Helmet",
+				"weight": 1.0,
+				"value": 1,
+				"health": 100,
+				"enchantment": 0,
+				"armor_rating": 1
+			}
+		}),
+		"Clothing" => json!({
+			"type": "Clothing",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"enchanting": "",
+			"biped_objects": [],
+			"data": { "clothing_type": "Pants", "weight": 1.0, "value": 1, "enchantment": 0 }
+		}),
+		"RepairItem" => json!({
+			"type": "RepairItem",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"data": { "weight": 1.0, "value": 1, "uses": 10, "quality": 1.0 }
+		}),
+		"Activator" => json!({
+			"type": "Activator",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": ""
+		}),
+		"Apparatus" => json!({
+			"type": "Apparatus",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"data": {
+				"apparatus_type": "MortarAndPestle",
+				"quality": 1.0,
+				"weight": 1.0,
+				"value": 1
+			}
+		}),
+		"Lockpick" => json!({
+			"type": "Lockpick",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"data": { "weight": 1.0, "value": 1, "quality": 1.0, "uses": 25 }
+		}),
+		"Probe" => json!({
+			"type": "Probe",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"data": { "weight": 1.0, "value": 1, "quality": 1.0, "uses": 25 }
+		}),
+		"Ingredient" => json!({
+			"type": "Ingredient",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"data": {
+				"weight": 1.0,
+				"value": 1,
+				"effects": ["None", "None", "None", "None"],
+				"skills": ["None", "None", "None", "None"],
+				"attributes": ["None", "None", "None", "None"]
+			}
+		}),
+		"Book" => json!({
+			"type": "Book",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"enchanting": "",
+			"text": "",
+			"data": {
+				"weight": 1.0,
+				"value": 1,
+				"book_type": "Book",
+				"skill": "None",
+				"enchantment": 0
+			}
+		}),
+		"Alchemy" => json!({
+			"type": "Alchemy",
+			"flags": "",
+			"id": key,
+			"name": key,
+			"script": "",
+			"mesh": "",
+			"icon": "",
+			"effects": [],
+			"data": { "weight": 1.0, "value": 1, "flags": "" }
+		}),
+		"LeveledItem" => json!({
+			"type": "LeveledItem",
+			"flags": "",
+			"id": key,
+			"leveled_item_flags": "",
+			"chance_none": 0,
+			"items": []
+		}),
+		"LeveledCreature" => json!({
+			"type": "LeveledCreature",
+			"flags": "",
+			"id": key,
+			"leveled_creature_flags": "",
+			"chance_none": 0,
+			"creatures": []
+		}),
+		"Cell" => match parse_grid(key) {
+			// Exterior cells are identified by their grid, interior ones by their name
+			Some(grid) => json!({
+				"type": "Cell",
+				"flags": "",
+				"name": "",
+				"data": { "flags": "", "grid": grid },
+				"references": []
+			}),
+			None => json!({
+				"type": "Cell",
+				"flags": "",
+				"name": key,
+				"data": { "flags": "IS_INTERIOR", "grid": [0, 0] },
+				"references": []
+			}),
+		},
+		"Landscape" => {
+			let grid = parse_grid(key)
+				.ok_or_else(|| anyhow!("a Landscape is identified by its grid, like `0,-1`"))?;
+			let encode = |data: Vec<u8>| base64::prelude::BASE64_STANDARD.encode(data);
+			// Flat land, with normals pointing straight up
+			let normals = [0u8, 0, 127].repeat(LANDSCAPE_VERTEX_COUNT);
+			json!({
+				"type": "Landscape",
+				"flags": "",
+				"grid": grid,
+				"landscape_flags": "USES_VERTEX_HEIGHTS_AND_NORMALS | USES_TEXTURES",
+				"vertex_normals": { "data": encode(normals) },
+				"vertex_heights": { "offset": 0.0, "data": encode(vec![0; LANDSCAPE_VERTEX_COUNT]) },
+				"world_map_data": { "data": encode(vec![0; 81]) },
+				"vertex_colors": { "data": encode(vec![255; LANDSCAPE_VERTEX_COUNT * 3]) },
+				"texture_indices": { "data": encode(vec![0; 512]) }
+			})
+		}
+		"PathGrid" => {
+			// tes3conv can't read an empty connection list, so it has a single unused entry
+			let (cell, grid) = match parse_grid(key) {
+				Some(grid) => ("".to_string(), grid),
+				None => (key.to_string(), [0, 0]),
+			};
+			json!({
+				"type": "PathGrid",
+				"flags": "",
+				"cell": cell,
+				"data": { "grid": grid, "granularity": 0, "point_count": 0 },
+				"points": [],
+				"connections": "AAAAAA=="
+			})
+		}
+		"Dialogue" => json!({
+			"type": "Dialogue",
+			"flags": "",
+			"id": key,
+			"dialogue_type": "Topic",
+			"dialogue_infos": []
+		}),
+		_ => return Err(anyhow!("unknown record type `{}`", record_type)),
+	};
+
+	Ok(record)
+}
@@ -0,0 +1,232 @@
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+	env, fs,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+	diagnostics::Diagnostics, format, get_master_index, process_directory, project::Project,
+	read_string_from_record, records,
+};
+
+// Save games refer to the references placed by the project by their refr_index. References keep
+// their index through compiles and decompiles, new ones get an index that was never used before,
+// which is written into their cell file once the plugin is built.
+
+// Every index the project has compiled, kept next to the records so it is shared through git
+pub const LEDGER_FILE: &str = "common/reference_ledger.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+	// The object the reference places, lowercase
+	pub id: String,
+	// The cell the reference was in when it was first compiled
+	pub cell: String,
+}
+
+// Indices compiled so far, indices of deleted references stay so they are never reused
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReferenceLedger {
+	indices: BTreeMap<u64, LedgerEntry>,
+	// Indices of references that were deleted, save games may still hold them
+	#[serde(default)]
+	retired: BTreeSet<u64>,
+}
+
+impl ReferenceLedger {
+	pub fn load() -> Self {
+		let mut path = env::current_dir().unwrap();
+		path.push(LEDGER_FILE);
+		fs::read_to_string(path)
+			.ok()
+			.and_then(|content| serde_json::from_str(&content).ok())
+			.unwrap_or_default()
+	}
+
+	pub fn save(&self) -> anyhow::Result<()> {
+		let mut path = env::current_dir().unwrap();
+		path.push(LEDGER_FILE);
+		fs::write(path, serde_json::to_string_pretty(self)?)?;
+		Ok(())
+	}
+
+	// Adds the references of the records and retires the indices that none of them use anymore,
+	// returns whether anything changed
+	pub fn record(&mut self, project: &Project, records: &[Value]) -> bool {
+		let mut changed = false;
+		let mut live = HashSet::new();
+		for record in records {
+			for (_, reference) in get_own_references(project, record) {
+				if let Some(index) = get_reference_index(reference) {
+					live.insert(index);
+					self.indices.entry(index).or_insert_with(|| {
+						changed = true;
+						get_ledger_entry(record, reference)
+					});
+				}
+			}
+		}
+		for index in self.indices.keys() {
+			if !live.contains(index) && self.retired.insert(*index) {
+				changed = true;
+			}
+		}
+		changed
+	}
+
+	// Follows a renamed object, so its references keep their indices, returns whether anything changed
+	pub fn rename_object(&mut self, old_id: &str, new_id: &str) -> bool {
+		let old_id = records::normalize_id(old_id);
+		let mut changed = false;
+		for entry in self.indices.values_mut() {
+			if entry.id == old_id {
+				entry.id = records::normalize_id(new_id);
+				changed = true;
+			}
+		}
+		changed
+	}
+
+	// The lowest index that neither the ledger nor the records use
+	pub fn next_free_index<'a>(
+		&self,
+		project: &Project,
+		records: impl IntoIterator<Item = &'a Value>,
+	) -> u64 {
+		let mut next = self.indices.keys().next_back().map_or(0, |index| index + 1);
+		for record in records {
+			for (_, reference) in get_own_references(project, record) {
+				if let Some(index) = get_reference_index(reference) {
+					next = next.max(index + 1);
+				}
+			}
+		}
+		next
+	}
+}
+
+// The refr_index of a reference, missing for references that were placed by hand
+pub fn get_reference_index(reference: &Value) -> Option<u64> {
+	reference.get("refr_index").and_then(|index| index.as_u64())
+}
+
+// References of a cell that belong to the project itself, along with their position in the array
+// In addons, references with a mast_index belong to a master
+fn get_own_references<'a>(
+	project: &'a Project,
+	record: &'a Value,
+) -> impl Iterator<Item = (usize, &'a Value)> + 'a {
+	let references = match read_string_from_record(record, "type").as_deref() {
+		Ok("Cell") => record.get("references").and_then(|references| references.as_array()),
+		_ => None,
+	};
+	references
+		.into_iter()
+		.flatten()
+		.enumerate()
+		.filter(|(_, reference)| reference.is_object())
+		.filter(|(_, reference)| !project.is_addon() || get_master_index(reference) == 0)
+}
+
+fn get_ledger_entry(cell: &Value, reference: &Value) -> LedgerEntry {
+	LedgerEntry {
+		id: records::normalize_id(&read_string_from_record(reference, "id").unwrap_or_default()),
+		cell: records::get_record_key(cell).unwrap_or_default(),
+	}
+}
+
+// Gives every reference in common/data that has no refr_index a new one and saves it in its cell file,
+// returns the files that were written
+// Files that can't be read are left to validation
+pub fn assign_missing_reference_indices(project: &Project, data_path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+	let mut files = vec![];
+	process_directory(data_path.to_path_buf(), &mut files)?;
+	let cells: Vec<_> = files
+		.into_iter()
+		.filter_map(|file| {
+			let record: Value = serde_json::from_str(&fs::read_to_string(&file).ok()?).ok()?;
+			(read_string_from_record(&record, "type").ok()? == "Cell").then_some((file, record))
+		})
+		.collect();
+
+	let mut next_index = ReferenceLedger::load().next_free_index(project, cells.iter().map(|(_, cell)| cell));
+	let mut written = vec![];
+	for (file, mut cell) in cells {
+		let missing: Vec<usize> = get_own_references(project, &cell)
+			.filter(|(_, reference)| get_reference_index(reference).is_none())
+			.map(|(index, _)| index)
+			.collect();
+		if missing.is_empty() {
+			continue;
+		}
+		for index in missing {
+			cell["references"][index]
+				.as_object_mut()
+				.unwrap()
+				.insert("refr_index".to_string(), next_index.into());
+			next_index += 1;
+		}
+		fs::write(&file, format::format_record(&cell))?;
+		eprintln!("Added reference indices to {}", file.to_string_lossy());
+		written.push(file);
+	}
+
+	Ok(written)
+}
+
+// Reports references that share an index, and indices that an earlier build used for another
+// object or for a reference that was deleted since
+pub fn check_reference_indices(
+	project: &Project,
+	records: &[Value],
+	ledger: &ReferenceLedger,
+	diagnostics: &mut Diagnostics,
+) {
+	let mut used: HashMap<u64, String> = HashMap::new();
+	for record in records {
+		let cell = records::get_record_key(record).unwrap_or_default();
+		for (position, reference) in get_own_references(project, record) {
+			let Some(index) = get_reference_index(reference) else {
+				continue;
+			};
+			let field = format!("/references/{}/refr_index", position);
+			if let Some(other_cell) = used.insert(index, cell.clone()) {
+				diagnostics.error(
+					record,
+					Some(&field),
+					format!(
+						"refr_index {} is used by another reference in cell {}, remove it to get a new one",
+						index, other_cell
+					),
+				);
+				continue;
+			}
+			if ledger.retired.contains(&index) {
+				diagnostics.error(
+					record,
+					Some(&field),
+					format!(
+						"refr_index {} belonged to a reference that was deleted, save games would mix them up, remove it to get a new one",
+						index
+					),
+				);
+				continue;
+			}
+			let entry = get_ledger_entry(record, reference);
+			match ledger.indices.get(&index) {
+				Some(previous) if previous.id != entry.id => diagnostics.error(
+					record,
+					Some(&field),
+					format!(
+						"refr_index {} was used for a reference to {} in cell {} by an earlier build, save games would mix them up, rename objects with `spicy rename-id` so {} follows them",
+						index, previous.id, previous.cell, LEDGER_FILE
+					),
+				),
+				_ => {}
+			}
+		}
+	}
+}
@@ -0,0 +1,521 @@
+use std::{env, fs, path::PathBuf};
+
+use anyhow::anyhow;
+use serde_json::Value;
+
+use crate::{
+	args::MessageFormat,
+	file_names, format, read_record_files, read_string_from_record,
+	reference_indices::{self, ReferenceLedger},
+	records::{self, ACTOR_TYPES, ITEM_TYPES, OBJECT_TYPES},
+};
+
+// Fields that hold ids of other records, as JSON pointers where `*` matches any array index,
+// along with the types of the records they can point at
+type ReferencePattern = (&'static str, &'static [&'static str]);
+
+fn get_reference_patterns(record_type: &str) -> &'static [ReferencePattern] {
+	match record_type {
+		"Cell" => &[
+			("/region", &["Region"]),
+			("/references/*/id", OBJECT_TYPES),
+			("/references/*/owner", &["Npc"]),
+			("/references/*/owner_global", &["GlobalVariable"]),
+			("/references/*/owner_faction", &["Faction"]),
+			("/references/*/soul", &["Creature"]),
+			("/references/*/key", &["MiscItem"]),
+			("/references/*/trap", &["Spell"]),
+			("/references/*/destination/cell", &["Cell"]),
+		],
+		"Npc" => &[
+			("/script", &["Script"]),
+			("/race", &["Race"]),
+			("/class", &["Class"]),
+			("/faction", &["Faction"]),
+			("/head", &["Bodypart"]),
+			("/hair", &["Bodypart"]),
+			("/spells/*", &["Spell"]),
+			("/inventory/*/1", ITEM_TYPES),
+			("/travel_destinations/*/cell", &["Cell"]),
+			("/ai_packages/*/target", ACTOR_TYPES),
+			("/ai_packages/*/cell", &["Cell"]),
+		],
+		"Creature" => &[
+			("/script", &["Script"]),
+			("/sound", &["Creature"]),
+			("/spells/*", &["Spell"]),
+			("/inventory/*/1", ITEM_TYPES),
+			("/travel_destinations/*/cell", &["Cell"]),
+			("/ai_packages/*/target", ACTOR_TYPES),
+			("/ai_packages/*/cell", &["Cell"]),
+		],
+		"Container" => &[("/script", &["Script"]), ("/inventory/*/1", ITEM_TYPES)],
+		"LeveledItem" => &[("/items/*/0", ITEM_TYPES)],
+		"LeveledCreature" => &[("/creatures/*/0", &["Creature", "LeveledCreature"])],
+		"Faction" => &[("/reactions/*/faction", &["Faction"])],
+		"Race" | "Birthsign" => &[("/spells/*", &["Spell"])],
+		"Bodypart" => &[("/race", &["Race"])],
+		"Region" => &[
+			("/sleep_creature", &["Creature", "LeveledCreature"]),
+			("/sounds/*/0", &["Sound"]),
+		],
+		"SoundGen" => &[("/creature", &["Creature"]), ("/sound", &["Sound"])],
+		"StartScript" => &[("/script", &["Script"])],
+		"PathGrid" => &[("/cell", &["Cell"])],
+		"Door" => &[
+			("/script", &["Script"]),
+			("/open_sound", &["Sound"]),
+			("/close_sound", &["Sound"]),
+		],
+		"Light" => &[("/script", &["Script"]), ("/sound", &["Sound"])],
+		"Weapon" | "Armor" | "Clothing" | "Book" => {
+			&[("/script", &["Script"]), ("/enchanting", &["Enchanting"])]
+		}
+		"Activator" | "Alchemy" | "Apparatus" | "Ingredient" | "Lockpick" | "MiscItem"
+		| "Probe" | "RepairItem" => &[("/script", &["Script"])],
+		"MagicEffect" => &[
+			("/bolt_sound", &["Sound"]),
+			("/cast_sound", &["Sound"]),
+			("/hit_sound", &["Sound"]),
+			("/area_sound", &["Sound"]),
+			("/cast_visual", &["Static"]),
+			("/bolt_visual", &["Static"]),
+			("/hit_visual", &["Static"]),
+			("/area_visual", &["Static"]),
+		],
+		// The types filters point at depend on the filter, see get_filter_targets
+		"Dialogue" => &[
+			("/dialogue_infos/*/speaker_id", ACTOR_TYPES),
+			("/dialogue_infos/*/speaker_race", &["Race"]),
+			("/dialogue_infos/*/speaker_class", &["Class"]),
+			("/dialogue_infos/*/speaker_faction", &["Faction"]),
+			("/dialogue_infos/*/speaker_cell", &["Cell"]),
+			("/dialogue_infos/*/player_faction", &["Faction"]),
+			("/dialogue_infos/*/filters/*/id", &[]),
+		],
+		_ => &[],
+	}
+}
+
+// Fields that hold script source code, ids are searched for as words in them
+pub fn get_script_patterns(record_type: &str) -> &'static [&'static str] {
+	match record_type {
+		"Script" => &["/text"],
+		"Dialogue" => &["/dialogue_infos/*/script_text"],
+		_ => &[],
+	}
+}
+
+// Turns a pattern into pointers of the fields that exist in the record
+fn expand_pattern(value: &Value, segments: &[&str], prefix: String, pointers: &mut Vec<String>) {
+	let Some((segment, rest)) = segments.split_first() else {
+		pointers.push(prefix);
+		return;
+	};
+	if *segment == "*" {
+		if let Some(array) = value.as_array() {
+			for (index, element) in array.iter().enumerate() {
+				expand_pattern(element, rest, format!("{}/{}", prefix, index), pointers);
+			}
+		}
+	} else if let Some(child) = value.get(*segment).or_else(|| {
+		segment
+			.parse::<usize>()
+			.ok()
+			.and_then(|index| value.get(index))
+	}) {
+		expand_pattern(child, rest, format!("{}/{}", prefix, segment), pointers);
+	}
+}
+
+pub fn expand_patterns(record: &Value, patterns: &[&str]) -> Vec<String> {
+	let mut pointers = vec![];
+	for pattern in patterns {
+		let segments: Vec<&str> = pattern.split('/').skip(1).collect();
+		expand_pattern(record, &segments, String::new(), &mut pointers);
+	}
+	pointers
+}
+
+// The types of the records a dialogue filter points at, local variables are named like ids but
+// they aren't records
+fn get_filter_targets(record: &Value, pointer: &str) -> &'static [&'static str] {
+	let filter_pointer = pointer.strip_suffix("/id").unwrap_or_default();
+	let filter_type = record
+		.pointer(&format!("{}/filter_type", filter_pointer))
+		.and_then(|filter_type| filter_type.as_str())
+		.unwrap_or_default();
+	match filter_type {
+		"Global" => &["GlobalVariable"],
+		"Journal" => &["Dialogue"],
+		"Item" => ITEM_TYPES,
+		"Dead" | "NotId" => ACTOR_TYPES,
+		"NotFaction" => &["Faction"],
+		"NotClass" => &["Class"],
+		"NotRace" => &["Race"],
+		"NotCell" => &["Cell"],
+		_ => &[],
+	}
+}
+
+// Pointers of all fields in the record that hold the id of a record of the given type
+// Without a type every field holding the id counts
+// Ids are case insensitive, same as in the game
+pub fn find_usages(record: &Value, id: &str, target_type: Option<&str>) -> Vec<String> {
+	let record_type = read_string_from_record(record, "type").unwrap_or_default();
+	let mut usages = vec![];
+	for (pattern, targets) in get_reference_patterns(&record_type) {
+		for pointer in expand_patterns(record, &[pattern]) {
+			let Some(value) = record.pointer(&pointer).and_then(|value| value.as_str()) else {
+				continue;
+			};
+			let targets = if pattern.ends_with("/filters/*/id") {
+				get_filter_targets(record, &pointer)
+			} else {
+				targets
+			};
+			let points_at_type = match target_type {
+				Some(target_type) => targets.contains(&target_type),
+				None => !targets.is_empty(),
+			};
+			if points_at_type && value.eq_ignore_ascii_case(id) {
+				usages.push(pointer);
+			}
+		}
+	}
+	for pointer in expand_patterns(record, get_script_patterns(&record_type)) {
+		let Some(text) = record.pointer(&pointer).and_then(|value| value.as_str()) else {
+			continue;
+		};
+		if !find_in_script(text, id, target_type).is_empty() {
+			usages.push(pointer);
+		}
+	}
+
+	usages
+}
+
+// Replaces the id of a record of the given type in every field that holds it, returns the number
+// of changed fields
+pub fn rename_usages(record: &mut Value, old_id: &str, new_id: &str, target_type: &str) -> usize {
+	let usages = find_usages(record, old_id, Some(target_type));
+	for pointer in &usages {
+		let value = record.pointer_mut(pointer).unwrap();
+		let renamed = match value.as_str() {
+			Some(text) if text.eq_ignore_ascii_case(old_id) => new_id.to_string(),
+			Some(text) => replace_in_script(text, old_id, new_id, target_type),
+			None => continue,
+		};
+		*value = renamed.into();
+	}
+
+	usages.len()
+}
+
+fn is_identifier_char(c: char) -> bool {
+	c.is_ascii_alphanumeric() || c == '_'
+}
+
+// Whether a script function takes ids of records of the type, None for functions that aren't known
+// to take ids
+fn script_function_takes(function: &str, target_type: &str) -> Option<bool> {
+	let targets: &[&str] = match function {
+		"begin" | "end" | "startscript" | "stopscript" | "scriptrunning" => &["Script"],
+		"addtopic" | "journal" | "setjournalindex" | "getjournalindex" => &["Dialogue"],
+		"addspell" | "removespell" | "getspell" | "cast" | "explodespell" | "getspelleffects"
+		| "removespelleffects" => &["Spell"],
+		"pcjoinfaction" | "pcraiserank" | "pclowerrank" | "pcexpell" | "pcexpelled"
+		| "pcclearexpelled" | "getpcrank" | "getpcfacrep" | "modpcfacrep" | "setpcfacrep"
+		| "getfactionreaction" | "modfactionreaction" | "setfactionreaction" => &["Faction"],
+		"getpccell" | "positioncell" => &["Cell"],
+		"playsound" | "playsoundvp" | "playsound3d" | "playsound3dvp" | "playloopsound3d"
+		| "playloopsound3dvp" | "stopsound" | "getsoundplaying" => &["Sound"],
+		"additem" | "removeitem" | "getitemcount" | "equip" | "hasitemequipped" | "drop" => {
+			ITEM_TYPES
+		}
+		"getdeadcount" | "getdetected" | "startcombat" | "aifollow" | "aiescort" => ACTOR_TYPES,
+		"placeatpc" | "placeatme" | "placeitem" | "aiactivate" | "getdistance" | "getlos" => {
+			OBJECT_TYPES
+		}
+		"placeitemcell" => return Some(target_type == "Cell" || OBJECT_TYPES.contains(&target_type)),
+		"aifollowcell" | "aiescortcell" => {
+			return Some(target_type == "Cell" || ACTOR_TYPES.contains(&target_type))
+		}
+		_ => return None,
+	};
+	Some(targets.contains(&target_type))
+}
+
+// Whether the word between the offsets can be the id of a record of the type, judged by how the
+// script uses it
+fn script_word_points_at(lowercase_text: &str, offset: usize, end: usize, target_type: &str) -> bool {
+	// References like `id->Function`
+	let rest = lowercase_text[end..].trim_start_matches('"').trim_start();
+	if rest.starts_with("->") {
+		return OBJECT_TYPES.contains(&target_type);
+	}
+
+	// Otherwise it is an argument of the closest function before it in the same expression
+	let line_start = lowercase_text[..offset].rfind('\n').map_or(0, |index| index + 1);
+	let mut expression = &lowercase_text[line_start..offset];
+	if let Some(index) = expression.rfind(['(', ')', '=', '<', '>', '!']) {
+		expression = &expression[index + 1..];
+	}
+	let function = expression
+		.split(|c: char| !is_identifier_char(c))
+		.rev()
+		.find_map(|word| script_function_takes(word, target_type));
+	match function {
+		Some(takes) => takes,
+		// Globals and objects are also used on their own, as in `set id to 1`
+		None => target_type == "GlobalVariable" || OBJECT_TYPES.contains(&target_type),
+	}
+}
+
+// Byte offsets of whole word occurrences of the id in script source
+// With a type only the ones that can point at a record of that type are returned
+fn find_in_script(text: &str, id: &str, target_type: Option<&str>) -> Vec<usize> {
+	let mut offsets = vec![];
+	if id.is_empty() {
+		return offsets;
+	}
+	let lowercase_text = text.to_ascii_lowercase();
+	let lowercase_id = id.to_ascii_lowercase();
+	let mut start = 0;
+	while let Some(found) = lowercase_text[start..].find(&lowercase_id) {
+		let offset = start + found;
+		let end = offset + lowercase_id.len();
+		let before = lowercase_text[..offset].chars().next_back();
+		let after = lowercase_text[end..].chars().next();
+		let is_word = !before.is_some_and(is_identifier_char) && !after.is_some_and(is_identifier_char);
+		if is_word
+			&& target_type.is_none_or(|target_type| {
+				script_word_points_at(&lowercase_text, offset, end, target_type)
+			}) {
+			offsets.push(offset);
+		}
+		start = end;
+	}
+
+	offsets
+}
+
+fn replace_in_script(text: &str, old_id: &str, new_id: &str, target_type: &str) -> String {
+	let mut result = String::new();
+	let mut last = 0;
+	for offset in find_in_script(text, old_id, Some(target_type)) {
+		result.push_str(&text[last..offset]);
+		result.push_str(new_id);
+		last = offset + old_id.len();
+	}
+	result.push_str(&text[last..]);
+	result
+}
+
+// Whether two records of these types would clash when they have the same key
+pub fn share_namespace(a: &str, b: &str) -> bool {
+	a == b || (records::is_object_type(a) && records::is_object_type(b))
+}
+
+// Renames a record and updates every reference to it
+// Records of different types can have the same id, the type picks one of them
+pub fn rename_id(old_id: &str, new_id: &str, record_type: Option<&str>) -> anyhow::Result<()> {
+	if new_id.is_empty() {
+		return Err(anyhow!("the new id can't be empty"));
+	}
+	let mut records = read_record_files(&get_data_path())?;
+
+	let matching: Vec<usize> = records
+		.iter()
+		.enumerate()
+		.filter(|(_, (_, record))| {
+			records::get_record_key(record).is_some_and(|key| key.eq_ignore_ascii_case(old_id))
+		})
+		.filter(|(_, (_, record))| {
+			record_type.is_none_or(|record_type| {
+				read_string_from_record(record, "type").is_ok_and(|other| other == record_type)
+			})
+		})
+		.map(|(index, _)| index)
+		.collect();
+	let target = match matching.as_slice() {
+		[] => return Err(anyhow!("no record with id `{}`", old_id)),
+		[index] => *index,
+		_ => {
+			let files: Vec<String> = matching
+				.iter()
+				.map(|index| records[*index].0.to_string_lossy().to_string())
+				.collect();
+			return Err(anyhow!(
+				"`{}` is defined more than once: {}, pass --type to pick one",
+				old_id,
+				files.join(", ")
+			));
+		}
+	};
+
+	// Only records identified by their id or name can be renamed
+	let target_type = read_string_from_record(&records[target].1, "type").unwrap_or_default();
+	let key_field = if records::uses_object_id(&target_type) {
+		"id"
+	} else if target_type == "Cell" && records::parse_grid(old_id).is_none() {
+		"name"
+	} else {
+		return Err(anyhow!("records of type {} can't be renamed", target_type));
+	};
+
+	if !old_id.eq_ignore_ascii_case(new_id) {
+		for (file, record) in &records {
+			let record_type = read_string_from_record(record, "type").unwrap_or_default();
+			let clashes = share_namespace(&record_type, &target_type)
+				&& records::get_record_key(record).is_some_and(|key| key.eq_ignore_ascii_case(new_id));
+			if clashes {
+				return Err(anyhow!(
+					"`{}` is already defined in {}",
+					new_id,
+					file.to_string_lossy()
+				));
+			}
+		}
+	}
+
+	// Rename the record itself, along with its file if it is named after the id
+	let (target_path, target_record) = &mut records[target];
+	target_record
+		.as_object_mut()
+		.unwrap()
+		.insert(key_field.to_string(), new_id.into());
+	let old_path = target_path.clone();
+	let file_stem = old_path
+		.file_stem()
+		.map(|stem| stem.to_string_lossy().to_string())
+		.unwrap_or_default();
+	if file_names::unescape_file_name(&file_stem).eq_ignore_ascii_case(old_id) {
+		let mut new_path = old_path.clone();
+		new_path.set_file_name(format!("{}.json", file_names::escape_file_name(new_id, false)));
+		if new_path.exists() && !new_id.eq_ignore_ascii_case(old_id) {
+			return Err(anyhow!("file {} already exists", new_path.to_string_lossy()));
+		}
+		*target_path = new_path;
+	}
+
+	// Then everything that refers to it
+	let mut changed = vec![target];
+	let mut usage_count = 0;
+	for (index, (_, record)) in records.iter_mut().enumerate() {
+		let count = rename_usages(record, old_id, new_id, &target_type);
+		if count > 0 {
+			usage_count += count;
+			if index != target {
+				changed.push(index);
+			}
+		}
+	}
+
+	for index in &changed {
+		let (path, record) = &records[*index];
+		fs::write(path, format::format_record(record))?;
+		println!("Updated {}", path.to_string_lossy());
+	}
+	// References placed by earlier builds keep their indices
+	if records::OBJECT_TYPES.contains(&target_type.as_str()) {
+		let mut reference_ledger = ReferenceLedger::load();
+		if reference_ledger.rename_object(old_id, new_id) {
+			reference_ledger.save()?;
+			let ledger_path = env::current_dir()?.join(reference_indices::LEDGER_FILE);
+			println!("Updated {}", ledger_path.to_string_lossy());
+		}
+	}
+	// On case insensitive file systems a rename that only changes case writes to the same file
+	let new_path = records[target].0.to_string_lossy().to_string();
+	if !new_path.eq_ignore_ascii_case(&old_path.to_string_lossy()) {
+		fs::remove_file(&old_path)?;
+		println!(
+			"Moved {} to {}",
+			old_path.to_string_lossy(),
+			records[target].0.to_string_lossy()
+		);
+	}
+	println!(
+		"Renamed `{}` to `{}`, updated {} references in {} files",
+		old_id,
+		new_id,
+		usage_count,
+		changed.len()
+	);
+
+	Ok(())
+}
+
+// Lists every field of every record that points at the id
+// Without a type, the type of the record that has the id is used
+pub fn print_usages(
+	id: &str,
+	record_type: Option<&str>,
+	message_format: MessageFormat,
+) -> anyhow::Result<()> {
+	let records = read_record_files(&get_data_path())?;
+
+	// Records of different types can have the same id, only look for usages of the one that has it
+	let mut target_types: Vec<String> = records
+		.iter()
+		.filter(|(_, record)| records::get_record_key(record).is_some_and(|key| key.eq_ignore_ascii_case(id)))
+		.map(|(_, record)| read_string_from_record(record, "type").unwrap_or_default())
+		.collect();
+	target_types.sort();
+	target_types.dedup();
+	let target_type = match target_types.as_slice() {
+		_ if record_type.is_some() => record_type,
+		[target_type] => Some(target_type.as_str()),
+		_ => None,
+	};
+
+	let mut usage_count = 0;
+	let mut file_count = 0;
+	for (file, record) in &records {
+		let usages = find_usages(record, id, target_type);
+		if usages.is_empty() {
+			continue;
+		}
+		file_count += 1;
+		usage_count += usages.len();
+
+		let record_type = read_string_from_record(record, "type").unwrap_or_default();
+		let record_key = records::get_record_key(record).unwrap_or_default();
+		match message_format {
+			MessageFormat::Human => {
+				println!("{} [{} {}]", file.to_string_lossy(), record_type, record_key);
+				for pointer in &usages {
+					println!("  {}", pointer);
+				}
+			}
+			MessageFormat::Json => {
+				for pointer in &usages {
+					let usage = serde_json::json!({
+						"file": file.to_string_lossy(),
+						"type": record_type,
+						"record_id": record_key,
+						"pointer": pointer,
+					});
+					println!("{}", usage);
+				}
+			}
+		}
+	}
+
+	if message_format == MessageFormat::Human {
+		if usage_count == 0 {
+			println!("No usages of `{}` found", id);
+		} else {
+			println!("\n{} usages in {} files", usage_count, file_count);
+		}
+	}
+
+	Ok(())
+}
+
+fn get_data_path() -> PathBuf {
+	let mut data_path = env::current_dir().unwrap();
+	data_path.push("common/data");
+	data_path
+}
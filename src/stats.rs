@@ -0,0 +1,311 @@
+use std::{
+	collections::{BTreeMap, HashSet},
+	env, fs,
+	path::{Path, PathBuf},
+};
+
+use colored::*;
+use serde_json::{json, Value};
+
+use crate::{
+	args::MessageFormat, dae::collect_files, project::Project, read_record_files,
+	read_string_from_record, references,
+};
+
+// Asset directories whose files are referenced by records
+const TRACKED_ASSET_DIRS: [&str; 5] = ["meshes", "icons", "textures", "sound", "music"];
+const LARGEST_FILE_COUNT: usize = 10;
+// openmw picks music from these directories by itself
+const ENGINE_MUSIC_DIRS: [&str; 3] = ["music/battle/", "music/explore/", "music/special/"];
+
+// Fields that hold asset paths, along with the assets subdirectory the paths are relative to
+fn get_asset_patterns(record_type: &str) -> &'static [(&'static str, &'static str)] {
+	match record_type {
+		"MagicEffect" => &[("/icon", "icons"), ("/texture", "textures")],
+		"Birthsign" => &[("/texture", "textures")],
+		"LandscapeTexture" => &[("/file_name", "textures")],
+		"Sound" => &[("/sound_path", "sound")],
+		"Dialogue" => &[("/dialogue_infos/*/sound_path", "sound")],
+		_ => &[("/mesh", "meshes"), ("/icon", "icons")],
+	}
+}
+
+// Assets are matched case-insensitively and without the extension, the same way openmw
+// falls back to dds textures and spicy compiles glb meshes to dae
+fn get_asset_key(subdir: &str, path: &str) -> String {
+	let path = format!("{}/{}", subdir, path.replace('\\', "/")).to_lowercase();
+	match Path::new(&path).extension() {
+		Some(_) if subdir != "sound" && subdir != "music" => {
+			Path::new(&path).with_extension("").to_string_lossy().to_string()
+		}
+		_ => path,
+	}
+}
+
+struct AssetFile {
+	// Path relative to the assets directory, with forward slashes
+	relative_path: String,
+	size: u64,
+}
+
+struct MissingAsset {
+	file: PathBuf,
+	pointer: String,
+	asset: String,
+}
+
+// Prints record counts, asset sizes and the assets that no record uses
+pub fn stats(project: &Project, message_format: MessageFormat) -> anyhow::Result<()> {
+	let mut data_path = env::current_dir().unwrap();
+	data_path.push("common/data");
+	let records = read_record_files(&data_path)?;
+	let assets = read_assets()?;
+
+	let mut record_counts: BTreeMap<String, usize> = BTreeMap::new();
+	for (_, record) in &records {
+		let record_type = read_string_from_record(record, "type").unwrap_or_default();
+		*record_counts.entry(record_type).or_default() += 1;
+	}
+
+	let mut folder_sizes: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+	for asset in &assets {
+		let folder = match asset.relative_path.split_once('/') {
+			Some((folder, _)) => folder.to_string(),
+			None => ".".to_string(),
+		};
+		let entry = folder_sizes.entry(folder).or_default();
+		entry.0 += 1;
+		entry.1 += asset.size;
+	}
+
+	let mut largest: Vec<&AssetFile> = assets.iter().collect();
+	largest.sort_by(|a, b| b.size.cmp(&a.size).then(a.relative_path.cmp(&b.relative_path)));
+	largest.truncate(LARGEST_FILE_COUNT);
+
+	let (unused, missing) = find_unused_and_missing(project, &records, &assets)?;
+
+	match message_format {
+		MessageFormat::Human => {
+			println!("{}", "Records".bold());
+			for (record_type, count) in &record_counts {
+				println!("  {:<20}{:>8}", record_type, count);
+			}
+			println!("  {:<20}{:>8}", "total", records.len());
+
+			println!("\n{}", "Assets".bold());
+			for (folder, (count, size)) in &folder_sizes {
+				println!("  {:<20}{:>8} files {:>12}", folder, count, format_size(*size));
+			}
+
+			println!("\n{}", "Largest assets".bold());
+			for asset in &largest {
+				println!("  {:>12}  assets/{}", format_size(asset.size), asset.relative_path);
+			}
+
+			println!("\n{} ({})", "Unused assets".bold(), unused.len());
+			for asset in &unused {
+				println!("  assets/{}", asset.relative_path);
+			}
+
+			println!("\n{} ({})", "Missing assets".bold(), missing.len());
+			for missing in &missing {
+				println!(
+					"  {} {}: {}",
+					missing.file.to_string_lossy(),
+					missing.pointer,
+					missing.asset.red()
+				);
+			}
+		}
+		MessageFormat::Json => {
+			for (record_type, count) in &record_counts {
+				println!("{}", json!({ "kind": "records", "type": record_type, "count": count }));
+			}
+			for (folder, (count, size)) in &folder_sizes {
+				println!(
+					"{}",
+					json!({ "kind": "asset_folder", "folder": folder, "files": count, "bytes": size })
+				);
+			}
+			for asset in &largest {
+				println!(
+					"{}",
+					json!({ "kind": "largest_asset", "path": asset.relative_path, "bytes": asset.size })
+				);
+			}
+			for asset in &unused {
+				println!("{}", json!({ "kind": "unused_asset", "path": asset.relative_path }));
+			}
+			for missing in &missing {
+				println!(
+					"{}",
+					json!({
+						"kind": "missing_asset",
+						"file": missing.file.to_string_lossy(),
+						"pointer": missing.pointer,
+						"asset": missing.asset,
+					})
+				);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+fn read_assets() -> anyhow::Result<Vec<AssetFile>> {
+	let mut assets_path = env::current_dir().unwrap();
+	assets_path.push("assets");
+	let mut files = vec![];
+	if assets_path.exists() {
+		collect_files(&assets_path, &mut files)?;
+	}
+	files.sort();
+
+	let mut assets = vec![];
+	for file in files {
+		let relative_path = file
+			.strip_prefix(&assets_path)?
+			.to_string_lossy()
+			.replace('\\', "/");
+		assets.push(AssetFile {
+			relative_path,
+			size: fs::metadata(&file)?.len(),
+		});
+	}
+
+	Ok(assets)
+}
+
+fn find_unused_and_missing<'a>(
+	project: &Project,
+	records: &[(PathBuf, Value)],
+	assets: &'a [AssetFile],
+) -> anyhow::Result<(Vec<&'a AssetFile>, Vec<MissingAsset>)> {
+	let existing: HashSet<String> = assets
+		.iter()
+		.filter_map(|asset| {
+			let (subdir, path) = asset.relative_path.split_once('/')?;
+			Some(get_asset_key(subdir, path))
+		})
+		.collect();
+
+	let mut used = HashSet::new();
+	let mut missing = vec![];
+	// Scripts and meshes refer to assets by file name, so they are searched as plain text
+	// along with the asset they came from, if any
+	let mut texts: Vec<(Option<&str>, String)> = vec![];
+	for (file, record) in records {
+		let record_type = read_string_from_record(record, "type").unwrap_or_default();
+		for (pattern, subdir) in get_asset_patterns(&record_type) {
+			for pointer in references::expand_patterns(record, &[pattern]) {
+				let Some(path) = record.pointer(&pointer).and_then(|path| path.as_str()) else {
+					continue;
+				};
+				if path.is_empty() {
+					continue;
+				}
+				let key = get_asset_key(subdir, path);
+				if !existing.contains(&key) {
+					missing.push(MissingAsset {
+						file: file.clone(),
+						pointer,
+						asset: format!("{}/{}", subdir, path),
+					});
+				}
+				used.insert(key);
+			}
+		}
+		let patterns = references::get_script_patterns(&record_type);
+		for pointer in references::expand_patterns(record, patterns) {
+			if let Some(text) = record.pointer(&pointer).and_then(|text| text.as_str()) {
+				texts.push((None, text.to_lowercase()));
+			}
+		}
+	}
+	for asset in assets {
+		// glb files refer to their textures from a json chunk, so they can be searched too
+		let is_text = asset.relative_path.starts_with("meshes/")
+			|| asset.relative_path.starts_with("scripts/")
+			|| asset.relative_path.ends_with(".omwscripts");
+		if is_text {
+			let mut path = env::current_dir().unwrap();
+			path.push("assets");
+			path.push(&asset.relative_path);
+			let text = String::from_utf8_lossy(&fs::read(path)?).to_lowercase();
+			texts.push((Some(&asset.relative_path), text));
+		}
+	}
+
+	let mut ignored: Vec<String> = project
+		.stats
+		.ignored_assets
+		.iter()
+		.map(|prefix| prefix.replace('\\', "/").to_lowercase())
+		.collect();
+	ignored.extend(ENGINE_MUSIC_DIRS.iter().map(|dir| dir.to_string()));
+	// Assets from the project template are loaded by openmw itself, like menu textures and sky meshes
+	let template_assets = get_template_assets();
+	let unused = assets
+		.iter()
+		.filter(|asset| {
+			let Some((subdir, path)) = asset.relative_path.split_once('/') else {
+				return false;
+			};
+			if !TRACKED_ASSET_DIRS.contains(&subdir) {
+				return false;
+			}
+			let lowercase_path = asset.relative_path.to_lowercase();
+			if ignored.iter().any(|prefix| lowercase_path.starts_with(prefix))
+				|| template_assets.contains(&lowercase_path)
+			{
+				return false;
+			}
+			if used.contains(&get_asset_key(subdir, path)) {
+				return false;
+			}
+			// Referenced by file name, for example from a mesh or from PlaySound in a script
+			let stem = Path::new(path)
+				.with_extension("")
+				.file_name()
+				.unwrap_or_default()
+				.to_string_lossy()
+				.to_lowercase();
+			!texts.iter().any(|(origin, text)| {
+				*origin != Some(asset.relative_path.as_str()) && text.contains(&stem)
+			})
+		})
+		.collect();
+
+	Ok((unused, missing))
+}
+
+// Lowercase paths of the assets new projects are created with
+fn get_template_assets() -> HashSet<String> {
+	let mut template_path = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+	template_path.push("res/template-assets");
+	let mut files = vec![];
+	if collect_files(&template_path, &mut files).is_err() {
+		return HashSet::new();
+	}
+	files
+		.iter()
+		.filter_map(|file| file.strip_prefix(&template_path).ok())
+		.map(|file| file.to_string_lossy().replace('\\', "/").to_lowercase())
+		.collect()
+}
+
+fn format_size(bytes: u64) -> String {
+	const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+	if unit == 0 {
+		format!("{} {}", bytes, UNITS[0])
+	} else {
+		format!("{:.1} {}", size, UNITS[unit])
+	}
+}
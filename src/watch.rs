@@ -0,0 +1,126 @@
+use std::{
+	env,
+	path::{Path, PathBuf},
+	sync::mpsc,
+	time::Duration,
+};
+
+use colored::*;
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::{
+	args::MessageFormat, cache::BuildCache, compile_records, dae, project::Project,
+	utils::create_subdirectory,
+};
+
+// How long to wait for more changes before rebuilding, editors tend to write files in bursts
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+#[derive(Default)]
+struct Changes {
+	data: bool,
+	assets: bool,
+}
+
+impl Changes {
+	fn add(
+		&mut self,
+		event: notify::Result<notify::Event>,
+		data_path: &Path,
+		assets_path: &Path,
+		cache: &BuildCache,
+	) {
+		let event = match event {
+			Ok(event) => event,
+			Err(err) => {
+				eprintln!("{}", format!("Watch error: {}", err).red());
+				return;
+			}
+		};
+		if matches!(event.kind, EventKind::Access(_)) {
+			return;
+		}
+		for path in event.paths {
+			if path.starts_with(data_path) {
+				// Compiling writes new info ids and reference indices into record files, the
+				// last build already read them, so they don't need another one
+				if cache.get_record(&path).is_none() {
+					self.data = true;
+				}
+			} else if path.starts_with(assets_path) {
+				self.assets = true;
+			}
+		}
+	}
+}
+
+// Watches records and assets, rebuilding the affected parts of the project on every change
+pub fn watch(project: &Project, message_format: MessageFormat) -> anyhow::Result<()> {
+	create_subdirectory("cache")?;
+	create_subdirectory("build")?;
+	let data_path = get_project_path("common/data");
+	let assets_path = get_project_path("assets");
+
+	let (sender, receiver) = mpsc::channel();
+	let mut watcher = notify::recommended_watcher(sender)?;
+	watcher.watch(&data_path, RecursiveMode::Recursive)?;
+	if assets_path.exists() {
+		watcher.watch(&assets_path, RecursiveMode::Recursive)?;
+	}
+
+	let mut cache = BuildCache::load();
+	rebuild(
+		project,
+		message_format,
+		&mut cache,
+		Changes {
+			data: true,
+			assets: true,
+		},
+	);
+
+	loop {
+		let mut changes = Changes::default();
+		changes.add(receiver.recv()?, &data_path, &assets_path, &cache);
+		while let Ok(event) = receiver.recv_timeout(DEBOUNCE) {
+			changes.add(event, &data_path, &assets_path, &cache);
+		}
+
+		if changes.data || changes.assets {
+			rebuild(project, message_format, &mut cache, changes);
+		}
+	}
+}
+
+// Errors are printed instead of returned, the watch should keep going until the user stops it
+fn rebuild(
+	project: &Project,
+	message_format: MessageFormat,
+	cache: &mut BuildCache,
+	changes: Changes,
+) {
+	if changes.assets {
+		eprintln!("\n=== compiling assets ===\n");
+		if let Err(err) = dae::compile_assets(cache) {
+			eprintln!("{}", format!("Failed to compile assets: {:#}", err).red());
+		}
+	}
+	if changes.data {
+		eprintln!("\n=== compiling records ===\n");
+		if let Err(err) = compile_records(project, message_format, cache) {
+			eprintln!("{}", format!("Failed to compile records: {:#}", err).red());
+		}
+	}
+
+	if let Err(err) = cache.save() {
+		eprintln!("{}", format!("Failed to save the build cache: {:#}", err).red());
+	}
+
+	eprintln!("{}", "Watching for changes...".green());
+}
+
+fn get_project_path(subpath: &str) -> PathBuf {
+	let mut path = env::current_dir().unwrap();
+	path.push(subpath);
+	path
+}
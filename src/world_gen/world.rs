@@ -41,13 +41,13 @@ impl OpenmwWorld {
 
 	// Value should be in heightmap adjusted tuds
 	pub fn set_elevation_canonical(&mut self, square: [i32; 2], value: f32) {
-		println!("{}", value);
+		eprintln!("{}", value);
 		// get the cell
 		let square_within_cell = get_coords_within_a_cell(square);
 		let cell = get_cell(&mut self.overworld_cells, square);
 		cell.set_elevation(
 			square_within_cell,
-			value as f32 * LAND_RECORD_SCALER / TODD_UNIT,
+			value * LAND_RECORD_SCALER / TODD_UNIT,
 		);
 	}
 
@@ -86,8 +86,5 @@ fn get_cell(
 	square: [i32; 2],
 ) -> &mut OpenmwCell {
 	let cell = get_cell_grid_coords(square);
-	if !overworld_cells.contains_key(&cell) {
-		overworld_cells.insert(cell, OpenmwCell::new());
-	}
-	overworld_cells.get_mut(&cell).unwrap()
+	overworld_cells.entry(cell).or_insert_with(OpenmwCell::new)
 }